@@ -53,7 +53,7 @@ async fn main() -> anyhow::Result<()> {
 	tracing::info!(url = ?config.url, "connecting to server");
 
 	let session = quic.client.connect(config.url).await?;
-	let mut session = moq_transfork::Session::connect(session).await?;
+	let mut session = quic::moq_connect(session).await?;
 
 	let track = Track::new(config.path);
 
@@ -53,7 +53,7 @@ impl<T: AsyncRead + Unpin> BroadcastClient<T> {
 		tracing::info!(?url, "connecting");
 
 		let session = quic.client.connect(url).await?;
-		let session = Session::connect(session).await?;
+		let session = quic::moq_connect(session).await?;
 
 		Ok(session)
 	}
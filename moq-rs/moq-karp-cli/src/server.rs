@@ -2,11 +2,9 @@ use super::{Config, FingerprintServer};
 
 use anyhow::Context;
 use moq_karp::cmaf::Import;
-use moq_karp::moq_transfork;
 use moq_karp::BroadcastProducer;
 use moq_native::quic;
 use moq_native::quic::Server;
-use moq_transfork::web_transport;
 use tokio::io::AsyncRead;
 
 pub struct BroadcastServer<T: AsyncRead + Unpin> {
@@ -67,10 +65,7 @@ impl<T: AsyncRead + Unpin> BroadcastServer<T> {
 		tokio::spawn(async move {
 			while let Some(conn) = server.accept().await {
 				// Create a new connection
-				let session: web_transport::Session = conn.into();
-				let transfork_session = moq_transfork::Session::accept(session)
-					.await
-					.expect("failed to accept session");
+				let transfork_session = quic::moq_accept(conn.session).await.expect("failed to accept session");
 
 				conn_id += 1;
 				broadcast.add_session(transfork_session).expect("failed to add session");
@@ -0,0 +1,375 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use mp4_atom::{
+	Dinf, Dref, Encode, FixedPoint, Ftyp, Hdlr, Mdat, Mdhd, Mdia, Mfhd, Minf, Moof, Moov, Mvex, Mvhd, Smhd, Stbl, Stco,
+	Tfdt, Tfhd, Tkhd, Traf, Trak, Trex, Url,
+};
+
+/// A single encoded sample and its duration in timescale units.
+#[derive(Clone, Debug)]
+pub struct Sample {
+	pub duration: u32,
+	pub payload: Bytes,
+}
+
+/// Converts Opus packets -> fMP4 (CMAF), one init segment followed by any number of fragments.
+///
+/// This is the minimal subset needed to package a single audio track for HLS/DASH.
+/// Each fragment is a `moof` + `mdat` pair that can be served as a standalone `.m4s` segment.
+pub struct Fragmenter {
+	track_id: u32,
+	channel_count: u8,
+	sample_rate: u32,
+
+	// The moof sequence number, incremented for each fragment.
+	sequence: u32,
+}
+
+impl Fragmenter {
+	/// The timescale used for all timestamps, as Opus always decodes at 48kHz.
+	pub const TIMESCALE: u32 = 48_000;
+
+	pub fn opus(channel_count: u8, sample_rate: u32) -> Self {
+		Self {
+			track_id: 1,
+			channel_count,
+			sample_rate,
+			sequence: 1,
+		}
+	}
+
+	/// Returns the init segment (ftyp + moov).
+	pub fn init(&self) -> Bytes {
+		let ftyp = Ftyp {
+			major_brand: b"iso6".into(),
+			minor_version: 0,
+			compatible_brands: vec![b"iso6".into(), b"cmfc".into(), b"mp41".into()],
+		};
+
+		let moov = Moov {
+			mvhd: Mvhd {
+				timescale: Self::TIMESCALE,
+				rate: FixedPoint::new(1, 0),
+				volume: FixedPoint::new(1, 0),
+				next_track_id: self.track_id + 1,
+				..Default::default()
+			},
+			trak: vec![Trak {
+				tkhd: Tkhd {
+					track_id: self.track_id,
+					alternate_group: 1,
+					enabled: true,
+					volume: FixedPoint::new(1, 0),
+					..Default::default()
+				},
+				mdia: Mdia {
+					mdhd: Mdhd {
+						timescale: Self::TIMESCALE,
+						language: "und".into(),
+						..Default::default()
+					},
+					hdlr: Hdlr {
+						handler: b"soun".into(),
+						name: "SoundHandler".into(),
+					},
+					minf: Minf {
+						smhd: Some(Smhd::default()),
+						dinf: Dinf {
+							dref: Dref {
+								urls: vec![Url::default()],
+							},
+						},
+						// Samples live in the fragments, so the tables are all empty.
+						// mp4_atom doesn't support Opus, so the sample entry is added to the stsd below.
+						stbl: Stbl {
+							stco: Some(Stco::default()),
+							..Default::default()
+						},
+						..Default::default()
+					},
+				},
+				..Default::default()
+			}],
+			mvex: Some(Mvex {
+				trex: vec![Trex {
+					track_id: self.track_id,
+					default_sample_description_index: 1,
+					..Default::default()
+				}],
+				..Default::default()
+			}),
+			..Default::default()
+		};
+
+		let mut buf = Vec::new();
+		ftyp.encode(&mut buf).expect("failed to encode ftyp");
+
+		let mut moov_buf = Vec::new();
+		moov.encode(&mut moov_buf).expect("failed to encode moov");
+
+		let stsd = append_box(
+			&mut moov_buf,
+			&[b"moov", b"trak", b"mdia", b"minf", b"stbl", b"stsd"],
+			&self.opus_sample_entry(),
+		);
+
+		// The stsd entry count follows the box header and version/flags.
+		let count = stsd + 12;
+		moov_buf[count..count + 4].copy_from_slice(&1u32.to_be_bytes());
+
+		buf.extend_from_slice(&moov_buf);
+		buf.into()
+	}
+
+	// https://opus-codec.org/docs/opus_in_isobmff.html
+	fn opus_sample_entry(&self) -> Vec<u8> {
+		let mut buf = BytesMut::new();
+
+		write_box(&mut buf, b"Opus", |buf| {
+			buf.put_bytes(0, 6); // reserved
+			buf.put_u16(1); // data reference index
+			buf.put_bytes(0, 8); // reserved
+			buf.put_u16(self.channel_count as u16);
+			buf.put_u16(16); // sample size
+			buf.put_u16(0); // pre-defined
+			buf.put_u16(0); // reserved
+			buf.put_u32(Self::TIMESCALE << 16); // sample rate, 16.16 fixed point
+
+			write_box(buf, b"dOps", |buf| {
+				buf.put_u8(0); // version
+				buf.put_u8(self.channel_count);
+				buf.put_u16(0); // pre-skip
+				buf.put_u32(self.sample_rate); // input sample rate
+				buf.put_i16(0); // output gain
+				buf.put_u8(0); // channel mapping family
+			});
+		});
+
+		buf.to_vec()
+	}
+
+	/// Returns a media segment (moof + mdat) containing the given samples.
+	///
+	/// The decode time is the timestamp of the first sample, in [Self::TIMESCALE] units.
+	pub fn fragment(&mut self, decode_time: u64, samples: &[Sample]) -> Bytes {
+		let moof = Moof {
+			mfhd: Mfhd {
+				sequence_number: self.sequence,
+			},
+			traf: vec![Traf {
+				tfhd: Tfhd {
+					track_id: self.track_id,
+					..Default::default()
+				},
+				tfdt: Some(Tfdt {
+					base_media_decode_time: decode_time,
+				}),
+				// Added below, see write_trun.
+				trun: None,
+			}],
+		};
+
+		let mut buf = Vec::new();
+		moof.encode(&mut buf).expect("failed to encode moof");
+
+		// The data offset is relative to the start of the moof (the default base), pointing past the mdat header.
+		let data_offset = buf.len() + trun_size(samples.len()) + 8;
+
+		let mut trun = BytesMut::new();
+		write_trun(&mut trun, data_offset as i32, samples);
+		append_box(&mut buf, &[b"moof", b"traf"], &trun);
+
+		let mdat = Mdat {
+			data: samples
+				.iter()
+				.flat_map(|sample| sample.payload.iter().copied())
+				.collect(),
+		};
+		mdat.encode(&mut buf).expect("failed to encode mdat");
+
+		self.sequence += 1;
+
+		buf.into()
+	}
+}
+
+// NOTE: mp4_atom 0.7 always writes first-sample-flags without setting the flag, producing an unreadable trun.
+// flags: data-offset-present | sample-duration-present | sample-size-present
+fn write_trun(buf: &mut BytesMut, data_offset: i32, samples: &[Sample]) {
+	write_box(buf, b"trun", |buf| {
+		buf.put_u32(0x00_0301); // version and flags
+		buf.put_u32(samples.len() as u32);
+		buf.put_i32(data_offset);
+
+		for sample in samples {
+			buf.put_u32(sample.duration);
+			buf.put_u32(sample.payload.len() as u32);
+		}
+	});
+}
+
+fn trun_size(samples: usize) -> usize {
+	// header, version/flags, sample count, data offset, then (duration, size) per sample
+	8 + 4 + 4 + 4 + samples * 8
+}
+
+fn write_box<F: FnOnce(&mut BytesMut)>(buf: &mut BytesMut, kind: &[u8; 4], body: F) {
+	let start = buf.len();
+	buf.put_u32(0); // size, filled in below
+	buf.put_slice(kind);
+
+	body(buf);
+
+	let size = (buf.len() - start) as u32;
+	buf[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+// Append a child to the end of a nested box, growing the size of every box on the path.
+// Returns the offset of the innermost box.
+fn append_box(buf: &mut Vec<u8>, path: &[&[u8; 4]], child: &[u8]) -> usize {
+	let mut parents = Vec::with_capacity(path.len());
+	let (mut start, mut end) = (0, buf.len());
+
+	for kind in path {
+		let parent = find_box(&buf[start..end], kind).expect("missing box") + start;
+		parents.push(parent);
+
+		// Only plain container boxes are walked into, so the children start after the 8 byte header.
+		start = parent + 8;
+		end = parent + box_size(buf, parent);
+	}
+
+	buf.splice(end..end, child.iter().copied());
+
+	for &parent in &parents {
+		let size = (box_size(buf, parent) + child.len()) as u32;
+		buf[parent..parent + 4].copy_from_slice(&size.to_be_bytes());
+	}
+
+	*parents.last().expect("empty path")
+}
+
+// Returns the offset of the first top-level box of the given kind.
+fn find_box(mut data: &[u8], kind: &[u8; 4]) -> Option<usize> {
+	let mut offset = 0;
+
+	while data.len() >= 8 {
+		let size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+		if &data[4..8] == kind {
+			return Some(offset);
+		}
+
+		if size < 8 || size > data.len() {
+			return None;
+		}
+
+		offset += size;
+		data = &data[size..];
+	}
+
+	None
+}
+
+fn box_size(buf: &[u8], offset: usize) -> usize {
+	u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap()) as usize
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use mp4_atom::{Any, Codec, Decode, StszSamples, TrunEntry};
+	use std::io::Cursor;
+
+	#[test]
+	fn init() {
+		let fragmenter = Fragmenter::opus(2, 48_000);
+		let init = fragmenter.init();
+
+		let mut input = Cursor::new(init.as_ref());
+		assert!(matches!(Any::decode(&mut input).unwrap(), Any::Ftyp(_)));
+
+		let moov = match Any::decode(&mut input).unwrap() {
+			Any::Moov(moov) => moov,
+			atom => panic!("unexpected atom: {:?}", atom),
+		};
+		assert_eq!(input.position() as usize, init.len(), "trailing data");
+
+		assert_eq!(moov.mvhd.timescale, Fragmenter::TIMESCALE);
+		assert_eq!(moov.mvex.unwrap().trex[0].track_id, 1);
+
+		let trak = &moov.trak[0];
+		assert_eq!(trak.tkhd.track_id, 1);
+		assert_eq!(trak.mdia.hdlr.handler, b"soun".into());
+
+		let stbl = &trak.mdia.minf.stbl;
+		assert_eq!(stbl.stsd.codecs, vec![Codec::Unknown(b"Opus".into())]);
+		assert_eq!(stbl.stsz.samples, StszSamples::Different { sizes: vec![] });
+
+		// The sample entry ends with the dOps box.
+		let dops = init.windows(4).position(|window| window == b"dOps").unwrap() - 4;
+		assert_eq!(box_size(&init, dops), 19);
+		assert_eq!(&init[dops + 8..dops + 19], &[0, 2, 0, 0, 0, 0, 0xbb, 0x80, 0, 0, 0]);
+	}
+
+	#[test]
+	fn fragment() {
+		let mut fragmenter = Fragmenter::opus(2, 48_000);
+
+		let samples = vec![
+			Sample {
+				duration: 960,
+				payload: Bytes::from_static(b"abc"),
+			},
+			Sample {
+				duration: 960,
+				payload: Bytes::from_static(b"defg"),
+			},
+		];
+
+		let segment = fragmenter.fragment(1920, &samples);
+
+		let mut input = Cursor::new(segment.as_ref());
+		let moof = match Any::decode(&mut input).unwrap() {
+			Any::Moof(moof) => moof,
+			atom => panic!("unexpected atom: {:?}", atom),
+		};
+		let moof_size = input.position() as usize;
+
+		assert_eq!(moof.mfhd.sequence_number, 1);
+
+		let traf = &moof.traf[0];
+		assert_eq!(traf.tfhd.track_id, 1);
+		assert_eq!(traf.tfdt.as_ref().unwrap().base_media_decode_time, 1920);
+
+		// The trun data offset should point at the first byte of the payload.
+		let trun = traf.trun.as_ref().unwrap();
+		assert_eq!(trun.data_offset, Some(moof_size as i32 + 8));
+		assert_eq!(
+			trun.entries,
+			vec![
+				TrunEntry {
+					duration: Some(960),
+					size: Some(3),
+					..Default::default()
+				},
+				TrunEntry {
+					duration: Some(960),
+					size: Some(4),
+					..Default::default()
+				},
+			]
+		);
+
+		match Any::decode(&mut input).unwrap() {
+			Any::Mdat(mdat) => assert_eq!(mdat.data, b"abcdefg"),
+			atom => panic!("unexpected atom: {:?}", atom),
+		};
+		assert_eq!(input.position() as usize, segment.len(), "trailing data");
+
+		// Each fragment gets the next sequence number.
+		let segment = fragmenter.fragment(3840, &samples);
+		match Any::decode(&mut Cursor::new(segment.as_ref())).unwrap() {
+			Any::Moof(moof) => assert_eq!(moof.mfhd.sequence_number, 2),
+			atom => panic!("unexpected atom: {:?}", atom),
+		};
+	}
+}
@@ -1,7 +1,9 @@
 mod error;
 //mod export;
+mod fragment;
 mod import;
 
 pub use error::*;
 //pub use export::*;
+pub use fragment::*;
 pub use import::*;
@@ -96,13 +96,28 @@ impl Endpoint {
 	}
 }
 
+/// A session accepted by the [Server].
+pub struct Accepted {
+	pub session: web_transport_quinn::Session,
+
+	/// The URL requested by the client, including any query parameters.
+	/// This is only available for WebTransport; raw QUIC clients don't send one.
+	pub url: Option<Url>,
+}
+
+impl From<Accepted> for web_transport::Session {
+	fn from(accepted: Accepted) -> Self {
+		accepted.session.into()
+	}
+}
+
 pub struct Server {
 	quic: quinn::Endpoint,
-	accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<web_transport_quinn::Session>>>,
+	accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<Accepted>>>,
 }
 
 impl Server {
-	pub async fn accept(&mut self) -> Option<web_transport_quinn::Session> {
+	pub async fn accept(&mut self) -> Option<Accepted> {
 		loop {
 			tokio::select! {
 				res = self.quic.accept() => {
@@ -118,7 +133,7 @@ impl Server {
 		}
 	}
 
-	async fn accept_session(conn: quinn::Incoming) -> anyhow::Result<web_transport_quinn::Session> {
+	async fn accept_session(conn: quinn::Incoming) -> anyhow::Result<Accepted> {
 		let mut conn = conn.accept()?;
 
 		let handshake = conn
@@ -139,25 +154,35 @@ impl Server {
 		let span = tracing::Span::current();
 		span.record("id", conn.stable_id()); // TODO can we get this earlier?
 
-		let session = match alpn.as_bytes() {
+		let accepted = match alpn.as_bytes() {
 			web_transport::quinn::ALPN => {
 				// Wait for the CONNECT request.
 				let request = web_transport::quinn::Request::accept(conn)
 					.await
 					.context("failed to receive WebTransport request")?;
 
+				let url = request.url().clone();
+
 				// Accept the CONNECT request.
-				request
+				let session = request
 					.ok()
 					.await
-					.context("failed to respond to WebTransport request")?
+					.context("failed to respond to WebTransport request")?;
+
+				Accepted {
+					session,
+					url: Some(url),
+				}
 			}
 			// A bit of a hack to pretend like we're a WebTransport session
-			moq_transfork::ALPN => conn.into(),
+			moq_transfork::ALPN => Accepted {
+				session: conn.into(),
+				url: None,
+			},
 			_ => anyhow::bail!("unsupported ALPN: {}", alpn),
 		};
 
-		Ok(session)
+		Ok(accepted)
 	}
 
 	pub fn local_addr(&self) -> anyhow::Result<net::SocketAddr> {
@@ -236,3 +261,36 @@ impl Client {
 		Ok(session)
 	}
 }
+
+/// Perform the MoqTransfork handshake as the client, including the connection statistics in [moq_transfork::Session::stats].
+pub async fn moq_connect(
+	session: web_transport_quinn::Session,
+) -> Result<moq_transfork::Session, moq_transfork::Error> {
+	let quic = session.clone();
+	let mut session = moq_transfork::Session::connect(session).await?;
+	session.set_connection_stats(move || stats(&quic));
+
+	Ok(session)
+}
+
+/// Perform the MoqTransfork handshake as the server, including the connection statistics in [moq_transfork::Session::stats].
+pub async fn moq_accept(session: web_transport_quinn::Session) -> Result<moq_transfork::Session, moq_transfork::Error> {
+	let quic = session.clone();
+	let mut session = moq_transfork::Session::accept(session).await?;
+	session.set_connection_stats(move || stats(&quic));
+
+	Ok(session)
+}
+
+/// Returns the connection statistics for a session, ex. for [moq_transfork::Session::set_connection_stats].
+pub fn stats(session: &web_transport_quinn::Session) -> moq_transfork::ConnectionStats {
+	let stats = session.stats();
+
+	moq_transfork::ConnectionStats {
+		rtt: stats.path.rtt,
+		cwnd: stats.path.cwnd,
+		bytes_sent: stats.udp_tx.bytes,
+		bytes_received: stats.udp_rx.bytes,
+		lost_packets: stats.path.lost_packets,
+	}
+}
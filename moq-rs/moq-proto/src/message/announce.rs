@@ -67,6 +67,34 @@ impl Encode for AnnouncePlease {
 	}
 }
 
+/// Sent by the subscriber on the announce stream when it won't use an announced track.
+/// For example, a relay that already has a publisher for the path.
+///
+/// The announcement is otherwise unaffected, so the track may still be used later.
+#[derive(Clone, Debug)]
+pub struct AnnounceError {
+	/// The contents of the wildcard.
+	pub suffix: String,
+
+	/// The reason, as an error code.
+	pub code: u32,
+}
+
+impl Decode for AnnounceError {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		let suffix = String::decode(r)?;
+		let code = u32::decode(r)?;
+		Ok(Self { suffix, code })
+	}
+}
+
+impl Encode for AnnounceError {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.suffix.encode(w);
+		self.code.encode(w);
+	}
+}
+
 impl Decode for AnnounceStatus {
 	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
 		let status = u8::decode(r)?;
@@ -0,0 +1,57 @@
+use crate::coding::*;
+
+/// An entire group sent as a single QUIC datagram, instead of a group stream.
+///
+/// This avoids the overhead of a stream for tiny groups, but there's no retransmission and it can arrive in any order.
+#[derive(Clone, Debug)]
+pub struct GroupDatagram {
+	// The subscribe ID.
+	pub subscribe: u64,
+
+	// The group sequence number
+	pub sequence: u64,
+
+	// Every frame in the group.
+	pub frames: Vec<bytes::Bytes>,
+}
+
+impl Decode for GroupDatagram {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(Self {
+			subscribe: u64::decode(r)?,
+			sequence: u64::decode(r)?,
+			frames: Vec::decode(r)?,
+		})
+	}
+}
+
+impl Encode for GroupDatagram {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.subscribe.encode(w);
+		self.sequence.encode(w);
+		self.frames.encode(w);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let msg = GroupDatagram {
+			subscribe: 7,
+			sequence: 1234,
+			frames: vec![bytes::Bytes::from_static(b"opus"), bytes::Bytes::new()],
+		};
+
+		let mut buf = Vec::new();
+		msg.encode(&mut buf);
+		assert_eq!(buf.len(), msg.encode_size());
+
+		let decoded = GroupDatagram::decode(&mut buf.as_slice()).unwrap();
+		assert_eq!(decoded.subscribe, 7);
+		assert_eq!(decoded.sequence, 1234);
+		assert_eq!(decoded.frames, msg.frames);
+	}
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use super::GroupOrder;
 use crate::coding::*;
 
@@ -6,6 +8,9 @@ pub struct Info {
 	pub priority: i8,
 	pub order: GroupOrder,
 	pub latest: u64,
+
+	/// Groups older than this aren't worth delivering, with millisecond precision.
+	pub ttl: Option<Duration>,
 }
 
 impl Encode for Info {
@@ -13,6 +18,10 @@ impl Encode for Info {
 		self.priority.encode(w);
 		self.order.encode(w);
 		self.latest.encode(w);
+
+		// Zero means there's no TTL, so round up to at least a millisecond.
+		let ttl = self.ttl.map(|ttl| (ttl.as_millis() as u64).max(1)).unwrap_or(0);
+		ttl.encode(w);
 	}
 }
 
@@ -21,11 +30,16 @@ impl Decode for Info {
 		let priority = i8::decode(r)?;
 		let order = GroupOrder::decode(r)?;
 		let latest = u64::decode(r)?;
+		let ttl = match u64::decode(r)? {
+			0 => None,
+			ms => Some(Duration::from_millis(ms)),
+		};
 
 		Ok(Self {
 			priority,
 			order,
 			latest,
+			ttl,
 		})
 	}
 }
@@ -47,3 +61,31 @@ impl Decode for InfoRequest {
 		Ok(Self { path })
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn ttl() {
+		let round_trip = |ttl| {
+			let info = Info {
+				priority: 1,
+				order: GroupOrder::Desc,
+				latest: 42,
+				ttl,
+			};
+
+			let mut buf = Vec::new();
+			info.encode(&mut buf);
+			Info::decode(&mut buf.as_slice()).unwrap().ttl
+		};
+
+		assert_eq!(round_trip(None), None);
+		assert_eq!(round_trip(Some(Duration::from_secs(10))), Some(Duration::from_secs(10)));
+		assert_eq!(
+			round_trip(Some(Duration::from_micros(10))),
+			Some(Duration::from_millis(1))
+		);
+	}
+}
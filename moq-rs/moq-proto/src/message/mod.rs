@@ -2,6 +2,7 @@
 //!
 //! This module could be used directly but 99% of the time you should use the higher-level [crate::Session] API.
 mod announce;
+mod datagram;
 mod extensions;
 mod filter;
 mod frame;
@@ -14,6 +15,7 @@ mod subscribe;
 mod versions;
 
 pub use announce::*;
+pub use datagram::*;
 pub use extensions::*;
 pub use filter::*;
 pub use frame::*;
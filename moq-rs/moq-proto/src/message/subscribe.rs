@@ -95,3 +95,28 @@ impl Encode for SubscribeUpdate {
 		self.end.map(|v| v + 1).unwrap_or(0).encode(w);
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let update = SubscribeUpdate {
+			priority: -3,
+			order: GroupOrder::Asc,
+			start: Some(0),
+			end: None,
+		};
+
+		let mut buf = Vec::new();
+		update.encode(&mut buf);
+
+		// A start of 0 is still a start, not unchanged.
+		let decoded = SubscribeUpdate::decode(&mut buf.as_slice()).unwrap();
+		assert_eq!(decoded.priority, -3);
+		assert_eq!(decoded.order, GroupOrder::Asc);
+		assert_eq!(decoded.start, Some(0));
+		assert_eq!(decoded.end, None);
+	}
+}
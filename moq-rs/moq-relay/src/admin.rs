@@ -0,0 +1,323 @@
+use std::time::UNIX_EPOCH;
+
+use axum::{
+	extract::{Path, Query, State},
+	http::{header, HeaderMap, StatusCode},
+	routing::{delete, get},
+	Json, Router,
+};
+use moq_transfork::{Error, SessionStats};
+use serde::{Deserialize, Serialize};
+
+use crate::{Auth, Cluster, Origins, Sessions};
+
+// Shared by every admin handler.
+#[derive(Clone)]
+struct Admin {
+	cluster: Cluster,
+	auth: Auth,
+}
+
+/// Routes for inspecting and managing the relay, requiring a token with the `admin` claim.
+///
+/// The admin API is unavailable when authentication is disabled.
+pub fn admin_routes(cluster: Cluster, auth: Auth) -> Router {
+	Router::new()
+		.route("/admin/sessions", get(list_sessions))
+		.route("/admin/sessions/{id}", delete(close_session))
+		.route("/admin/announced", get(list_announced))
+		.route("/admin/announced/{*path}", delete(unannounce))
+		.route("/admin/subscriptions", get(list_subscriptions))
+		.with_state(Admin { cluster, auth })
+}
+
+#[derive(Deserialize)]
+struct AdminQuery {
+	/// Used if there's no `Authorization: Bearer` header.
+	jwt: Option<String>,
+}
+
+impl Admin {
+	fn authorize(&self, headers: &HeaderMap, query: &AdminQuery) -> Result<(), StatusCode> {
+		authorize(&self.auth, headers, query)
+	}
+}
+
+fn authorize(auth: &Auth, headers: &HeaderMap, query: &AdminQuery) -> Result<(), StatusCode> {
+	let bearer = headers
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+
+	let token = match bearer.or(query.jwt.as_deref()) {
+		Some(token) => token,
+		None => return Err(StatusCode::UNAUTHORIZED),
+	};
+
+	match auth.verify_token(Some(token)) {
+		Ok(permissions) if permissions.is_admin() => Ok(()),
+		Ok(_) => Err(StatusCode::FORBIDDEN),
+		Err(_) => Err(StatusCode::UNAUTHORIZED),
+	}
+}
+
+#[derive(Serialize)]
+struct SessionJson {
+	id: u64,
+	// The peer address, or None for remote cluster nodes we connected to.
+	addr: Option<String>,
+	// The hostname of the remote cluster node, if any.
+	node: Option<String>,
+	// Seconds since the UNIX epoch.
+	connected: u64,
+	stats: StatsJson,
+}
+
+#[derive(Serialize)]
+struct StatsJson {
+	// Only known for QUIC connections.
+	rtt_ms: Option<u128>,
+	bytes_sent: Option<u64>,
+	bytes_received: Option<u64>,
+	lost_packets: Option<u64>,
+
+	streams_sent: u64,
+	streams_received: u64,
+	datagrams_sent: u64,
+	datagrams_received: u64,
+	serving: usize,
+	subscribed: usize,
+	groups_in_flight: usize,
+}
+
+impl From<SessionStats> for StatsJson {
+	fn from(stats: SessionStats) -> Self {
+		let connection = stats.connection.as_ref();
+
+		Self {
+			rtt_ms: connection.map(|conn| conn.rtt.as_millis()),
+			bytes_sent: connection.map(|conn| conn.bytes_sent),
+			bytes_received: connection.map(|conn| conn.bytes_received),
+			lost_packets: connection.map(|conn| conn.lost_packets),
+			streams_sent: stats.streams_sent,
+			streams_received: stats.streams_received,
+			datagrams_sent: stats.datagrams_sent,
+			datagrams_received: stats.datagrams_received,
+			serving: stats.serving,
+			subscribed: stats.subscribed,
+			groups_in_flight: stats.groups_in_flight,
+		}
+	}
+}
+
+async fn list_sessions(
+	State(admin): State<Admin>,
+	headers: HeaderMap,
+	Query(query): Query<AdminQuery>,
+) -> Result<Json<Vec<SessionJson>>, StatusCode> {
+	admin.authorize(&headers, &query)?;
+
+	let sessions = admin
+		.cluster
+		.sessions
+		.list()
+		.into_iter()
+		.map(|info| SessionJson {
+			id: info.id,
+			addr: info.addr.map(|addr| addr.to_string()),
+			node: info.node,
+			connected: info
+				.connected
+				.duration_since(UNIX_EPOCH)
+				.map(|since| since.as_secs())
+				.unwrap_or_default(),
+			stats: info.session.stats().into(),
+		})
+		.collect();
+
+	Ok(Json(sessions))
+}
+
+async fn close_session(
+	State(admin): State<Admin>,
+	headers: HeaderMap,
+	Query(query): Query<AdminQuery>,
+	Path(id): Path<u64>,
+) -> Result<StatusCode, StatusCode> {
+	admin.authorize(&headers, &query)?;
+
+	let info = admin.cluster.sessions.get(id).ok_or(StatusCode::NOT_FOUND)?;
+
+	tracing::warn!(id, "closing session via admin API");
+	info.session.close(Error::Cancel);
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+struct AnnouncedJson {
+	path: String,
+	// True if the path was announced by another cluster node.
+	remote: bool,
+	// The IDs of the sessions that announced the path, or None if it was this relay.
+	origins: Vec<Option<u64>>,
+}
+
+async fn list_announced(
+	State(admin): State<Admin>,
+	headers: HeaderMap,
+	Query(query): Query<AdminQuery>,
+) -> Result<Json<Vec<AnnouncedJson>>, StatusCode> {
+	admin.authorize(&headers, &query)?;
+
+	let sessions = &admin.cluster.sessions;
+
+	let mut announced = list_origins(&admin.cluster.locals, sessions, false);
+	announced.extend(list_origins(&admin.cluster.remotes, sessions, true));
+
+	Ok(Json(announced))
+}
+
+fn list_origins(origins: &Origins, sessions: &Sessions, remote: bool) -> Vec<AnnouncedJson> {
+	origins
+		.list()
+		.into_iter()
+		.map(|(path, origins)| AnnouncedJson {
+			path,
+			remote,
+			origins: origins
+				.iter()
+				.map(|origin| origin.as_ref().and_then(|session| sessions.find(session)))
+				.collect(),
+		})
+		.collect()
+}
+
+async fn unannounce(
+	State(mut admin): State<Admin>,
+	headers: HeaderMap,
+	Query(query): Query<AdminQuery>,
+	Path(path): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+	admin.authorize(&headers, &query)?;
+
+	// NOTE: Don't short-circuit, in case both locals and remotes announced the path.
+	let local = admin.cluster.locals.unannounce(&path);
+	let remote = admin.cluster.remotes.unannounce(&path);
+
+	match local || remote {
+		true => Ok(StatusCode::NO_CONTENT),
+		false => Err(StatusCode::NOT_FOUND),
+	}
+}
+
+#[derive(Serialize)]
+struct SubscriptionJson {
+	// The session that is subscribed.
+	session: u64,
+	id: u64,
+	path: String,
+	priority: i8,
+	// Either "asc" or "desc".
+	order: &'static str,
+	groups: u64,
+	bytes: u64,
+}
+
+async fn list_subscriptions(
+	State(admin): State<Admin>,
+	headers: HeaderMap,
+	Query(query): Query<AdminQuery>,
+) -> Result<Json<Vec<SubscriptionJson>>, StatusCode> {
+	admin.authorize(&headers, &query)?;
+
+	let mut subscriptions = Vec::new();
+
+	for info in admin.cluster.sessions.list() {
+		for subscription in info.session.subscriptions() {
+			subscriptions.push(SubscriptionJson {
+				session: info.id,
+				id: subscription.id,
+				path: subscription.path,
+				priority: subscription.priority,
+				order: match subscription.order {
+					moq_transfork::GroupOrder::Asc => "asc",
+					moq_transfork::GroupOrder::Desc => "desc",
+				},
+				groups: subscription.groups,
+				bytes: subscription.bytes,
+			});
+		}
+	}
+
+	Ok(Json(subscriptions))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::auth::test::{auth, token, TestClaims};
+	use crate::AuthConfig;
+
+	fn bearer(token: &str) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+		headers
+	}
+
+	#[test]
+	fn authorize_admin() {
+		let auth = auth(&[]);
+		let admin = token(TestClaims {
+			admin: true,
+			..Default::default()
+		});
+
+		let query = AdminQuery { jwt: None };
+		assert_eq!(authorize(&auth, &bearer(&admin), &query), Ok(()));
+
+		// The query parameter is used if there's no header.
+		let query = AdminQuery { jwt: Some(admin) };
+		assert_eq!(authorize(&auth, &HeaderMap::new(), &query), Ok(()));
+	}
+
+	#[test]
+	fn authorize_rejected() {
+		let auth = auth(&[]);
+		let query = AdminQuery { jwt: None };
+
+		assert_eq!(
+			authorize(&auth, &HeaderMap::new(), &query),
+			Err(StatusCode::UNAUTHORIZED)
+		);
+		assert_eq!(
+			authorize(&auth, &bearer("garbage"), &query),
+			Err(StatusCode::UNAUTHORIZED)
+		);
+
+		// A valid token without the admin claim, even one that can publish everything.
+		let user = token(TestClaims {
+			publish: vec!["".to_string()],
+			cluster: true,
+			..Default::default()
+		});
+		assert_eq!(authorize(&auth, &bearer(&user), &query), Err(StatusCode::FORBIDDEN));
+	}
+
+	#[test]
+	fn authorize_disabled() {
+		// The admin API is unavailable when authentication is disabled, no matter the token.
+		let config = AuthConfig {
+			auth_key: None,
+			auth_public: Vec::new(),
+		};
+		let auth = Auth::new(config, None).unwrap();
+
+		let admin = token(TestClaims {
+			admin: true,
+			..Default::default()
+		});
+		let query = AdminQuery { jwt: None };
+		assert_eq!(authorize(&auth, &bearer(&admin), &query), Err(StatusCode::FORBIDDEN));
+	}
+}
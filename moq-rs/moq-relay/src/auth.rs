@@ -0,0 +1,349 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::Parser;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use url::Url;
+
+/// Paths reserved for cluster peers, ex. `internal/origins/<node>`.
+pub const INTERNAL_PREFIX: &str = "internal/";
+
+#[derive(Clone, Parser)]
+pub struct AuthConfig {
+	/// Require clients to present a JWT signed with the HMAC-SHA256 secret in this file.
+	/// The token is provided via the `jwt` query parameter, ex. `https://relay/?jwt=<token>`.
+	/// If not provided, then authentication is disabled and anybody can publish or subscribe to anything.
+	#[arg(long)]
+	pub auth_key: Option<PathBuf>,
+
+	/// Allow anybody to subscribe to paths with this prefix, even without a token.
+	/// Can be provided multiple times.
+	#[arg(long)]
+	pub auth_public: Vec<String>,
+}
+
+/// The claims we expect in the JWT, on top of the standard `exp`.
+#[derive(Debug, Deserialize)]
+struct Claims {
+	/// Path prefixes the client is allowed to announce.
+	#[serde(default)]
+	publish: Vec<String>,
+
+	/// Path prefixes the client is allowed to subscribe to.
+	#[serde(default)]
+	subscribe: Vec<String>,
+
+	/// Whether the client can use the admin API.
+	#[serde(default)]
+	admin: bool,
+
+	/// Whether the client is another cluster node, allowed to use internal paths.
+	#[serde(default)]
+	cluster: bool,
+}
+
+/// What a session is allowed to do, as a list of path prefixes.
+#[derive(Clone, Debug, Default)]
+pub struct Permissions {
+	publish: Vec<String>,
+	subscribe: Vec<String>,
+	admin: bool,
+	cluster: bool,
+}
+
+impl Permissions {
+	/// Allow everything, used for cluster internals.
+	///
+	/// This does not include the admin API, which requires an explicit `admin` claim.
+	pub fn full() -> Self {
+		Self {
+			publish: vec!["".to_string()],
+			subscribe: vec!["".to_string()],
+			admin: false,
+			cluster: true,
+		}
+	}
+
+	pub fn can_publish(&self, path: &str) -> bool {
+		self.can_use(path) && self.publish.iter().any(|prefix| path.starts_with(prefix))
+	}
+
+	pub fn can_subscribe(&self, path: &str) -> bool {
+		self.can_use(path) && self.subscribe.iter().any(|prefix| path.starts_with(prefix))
+	}
+
+	// Internal paths are only available to cluster peers, regardless of any prefixes.
+	fn can_use(&self, path: &str) -> bool {
+		self.cluster || !path.starts_with(INTERNAL_PREFIX)
+	}
+
+	pub fn is_admin(&self) -> bool {
+		self.admin
+	}
+}
+
+#[derive(Clone)]
+pub struct Auth {
+	// None if authentication is disabled.
+	key: Option<DecodingKey>,
+	public: Vec<String>,
+
+	// The token other cluster nodes present, used to identify them when authentication is disabled.
+	cluster: Option<String>,
+}
+
+impl Auth {
+	/// The cluster token is the file used to connect to other nodes, see [crate::ClusterConfig].
+	pub fn new(config: AuthConfig, cluster_token: Option<&Path>) -> anyhow::Result<Self> {
+		let key = match config.auth_key {
+			Some(path) => {
+				let secret = fs::read(&path).with_context(|| format!("failed to read auth key: {}", path.display()))?;
+				Some(DecodingKey::from_secret(secret.trim_ascii()))
+			}
+			None => None,
+		};
+
+		let cluster = match cluster_token {
+			Some(path) => {
+				let token = fs::read_to_string(path).context("failed to read cluster token")?;
+				Some(token.trim().to_string())
+			}
+			None => None,
+		};
+
+		Ok(Self {
+			key,
+			public: config.auth_public,
+			cluster,
+		})
+	}
+
+	/// Verify the token in the URL (if any), returning what the session is allowed to do.
+	pub fn verify(&self, url: Option<&Url>) -> anyhow::Result<Permissions> {
+		let token = url.and_then(|url| {
+			url.query_pairs()
+				.find(|(name, _)| name == "jwt")
+				.map(|(_, token)| token)
+		});
+		self.verify_token(token.as_deref())
+	}
+
+	/// Verify an optional token, returning what the holder is allowed to do.
+	///
+	/// Clients without a token can only subscribe to public paths.
+	/// An invalid or expired token is an error, rather than silently downgrading to public.
+	///
+	/// When authentication is disabled anybody can publish or subscribe, but internal paths still require the cluster token.
+	pub fn verify_token(&self, token: Option<&str>) -> anyhow::Result<Permissions> {
+		let key = match &self.key {
+			Some(key) => key,
+			None => {
+				let cluster = token.is_some() && token == self.cluster.as_deref();
+				return Ok(Permissions {
+					cluster,
+					..Permissions::full()
+				});
+			}
+		};
+
+		let mut permissions = match token {
+			Some(token) => {
+				let validation = Validation::new(Algorithm::HS256);
+				let token = jsonwebtoken::decode::<Claims>(token, key, &validation).context("invalid token")?;
+
+				Permissions {
+					publish: token.claims.publish,
+					subscribe: token.claims.subscribe,
+					admin: token.claims.admin,
+					cluster: token.claims.cluster,
+				}
+			}
+			None => Permissions::default(),
+		};
+
+		permissions.subscribe.extend(self.public.iter().cloned());
+
+		Ok(permissions)
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use super::*;
+	use jsonwebtoken::{EncodingKey, Header};
+	use serde::Serialize;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::time::{SystemTime, UNIX_EPOCH};
+
+	const SECRET: &[u8] = b"test-secret";
+
+	#[derive(Default, Serialize)]
+	pub struct TestClaims {
+		pub publish: Vec<String>,
+		pub subscribe: Vec<String>,
+		pub admin: bool,
+		pub cluster: bool,
+	}
+
+	#[derive(Serialize)]
+	struct Signed {
+		#[serde(flatten)]
+		claims: TestClaims,
+		exp: u64,
+	}
+
+	// Sign a token that expires in an hour.
+	pub fn token(claims: TestClaims) -> String {
+		sign(SECRET, claims, expires_in(3600))
+	}
+
+	fn sign(secret: &[u8], claims: TestClaims, exp: u64) -> String {
+		let key = EncodingKey::from_secret(secret);
+		jsonwebtoken::encode(&Header::default(), &Signed { claims, exp }, &key).unwrap()
+	}
+
+	fn expires_in(secs: i64) -> u64 {
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+		now.saturating_add_signed(secs)
+	}
+
+	// Authentication enabled with the test secret, read from a key file like in production.
+	pub fn auth(public: &[&str]) -> Auth {
+		// Tests run in parallel, so each gets its own file.
+		static NEXT: AtomicUsize = AtomicUsize::new(0);
+		let name = format!(
+			"moq-relay-test-{}-{}.key",
+			std::process::id(),
+			NEXT.fetch_add(1, Ordering::Relaxed)
+		);
+		let path = std::env::temp_dir().join(name);
+		fs::write(&path, [SECRET, b"\n"].concat()).unwrap();
+
+		let auth = Auth::new(
+			AuthConfig {
+				auth_key: Some(path.clone()),
+				auth_public: public.iter().map(|prefix| prefix.to_string()).collect(),
+			},
+			None,
+		)
+		.unwrap();
+
+		// The key is only read on startup.
+		fs::remove_file(&path).unwrap();
+
+		auth
+	}
+
+	#[test]
+	fn claims() {
+		let auth = auth(&[]);
+		let token = token(TestClaims {
+			publish: vec!["station1/".to_string()],
+			subscribe: vec!["station".to_string()],
+			..Default::default()
+		});
+
+		let permissions = auth.verify_token(Some(&token)).unwrap();
+		assert!(permissions.can_publish("station1/audio"));
+		assert!(!permissions.can_publish("station2/audio"));
+		assert!(permissions.can_subscribe("station2/audio"));
+		assert!(!permissions.can_subscribe("other"));
+		assert!(!permissions.is_admin());
+	}
+
+	#[test]
+	fn public() {
+		let auth = auth(&["public/"]);
+
+		// Anybody can subscribe to public paths, but nothing else.
+		let permissions = auth.verify_token(None).unwrap();
+		assert!(permissions.can_subscribe("public/station1"));
+		assert!(!permissions.can_subscribe("station1"));
+		assert!(!permissions.can_publish("public/station1"));
+
+		let url = Url::parse("https://relay/").unwrap();
+		let permissions = auth.verify(Some(&url)).unwrap();
+		assert!(permissions.can_subscribe("public/station1"));
+	}
+
+	#[test]
+	fn invalid() {
+		let auth = auth(&["public/"]);
+
+		// Bad tokens are an error instead of falling back to public.
+		assert!(auth.verify_token(Some("garbage")).is_err());
+
+		// Past the default leeway of a minute.
+		let expired = sign(SECRET, TestClaims::default(), expires_in(-3600));
+		assert!(auth.verify_token(Some(&expired)).is_err());
+
+		let admin = TestClaims {
+			admin: true,
+			..Default::default()
+		};
+		let forged = sign(b"wrong-secret", admin, expires_in(3600));
+		assert!(auth.verify_token(Some(&forged)).is_err());
+	}
+
+	#[test]
+	fn internal() {
+		let auth = auth(&[""]);
+
+		// Internal paths need the cluster claim, even if a prefix would otherwise match.
+		let user = token(TestClaims {
+			publish: vec!["".to_string()],
+			..Default::default()
+		});
+		let permissions = auth.verify_token(Some(&user)).unwrap();
+		assert!(permissions.can_publish("station1"));
+		assert!(!permissions.can_publish("internal/origins/node1"));
+		assert!(!permissions.can_subscribe("internal/origins/node1"));
+
+		let peer = token(TestClaims {
+			cluster: true,
+			subscribe: vec!["".to_string()],
+			..Default::default()
+		});
+		let permissions = auth.verify_token(Some(&peer)).unwrap();
+		assert!(permissions.can_subscribe("internal/origins/node1"));
+	}
+
+	#[test]
+	fn disabled() {
+		let path = std::env::temp_dir().join(format!("moq-relay-test-{}.token", std::process::id()));
+		fs::write(&path, "cluster-secret\n").unwrap();
+
+		let config = AuthConfig {
+			auth_key: None,
+			auth_public: Vec::new(),
+		};
+		let auth = Auth::new(config, Some(&path)).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		// Anybody can use normal paths, but internal paths are refused without the cluster token.
+		for token in [None, Some("garbage")] {
+			let permissions = auth.verify_token(token).unwrap();
+			assert!(permissions.can_publish("station1"));
+			assert!(permissions.can_subscribe("station1"));
+			assert!(!permissions.can_publish("internal/origins/node1"));
+			assert!(!permissions.can_subscribe("internal/origins/node1"));
+		}
+
+		let url = Url::parse("https://relay/?jwt=cluster-secret").unwrap();
+		let permissions = auth.verify(Some(&url)).unwrap();
+		assert!(permissions.can_publish("internal/origins/node1"));
+		assert!(permissions.can_subscribe("internal/origins/node1"));
+
+		// Without a cluster token configured, nobody is a cluster node.
+		let config = AuthConfig {
+			auth_key: None,
+			auth_public: Vec::new(),
+		};
+		let auth = Auth::new(config, None).unwrap();
+		assert!(!auth.verify_token(None).unwrap().can_publish("internal/origins/node1"));
+	}
+}
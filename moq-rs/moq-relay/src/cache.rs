@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use clap::Parser;
+use moq_transfork::Retention;
+
+#[derive(Clone, Parser)]
+pub struct CacheConfig {
+	/// Keep up to this many older groups per track, in addition to the latest.
+	/// Subscribers can request them via the subscribe start/end range, ex. when they join late or reconnect.
+	#[arg(long, default_value = "0")]
+	pub cache_groups: usize,
+
+	/// Limit the older groups kept per track to this many bytes.
+	#[arg(long)]
+	pub cache_bytes: Option<usize>,
+
+	/// Evict older groups after this many seconds.
+	#[arg(long)]
+	pub cache_age: Option<u64>,
+}
+
+impl CacheConfig {
+	pub fn retention(&self) -> Retention {
+		Retention {
+			groups: self.cache_groups,
+			bytes: self.cache_bytes,
+			age: self.cache_age.map(Duration::from_secs),
+		}
+	}
+}
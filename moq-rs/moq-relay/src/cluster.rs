@@ -1,22 +1,51 @@
-use std::collections::HashMap;
+use std::{
+	collections::{hash_map, HashMap, HashSet},
+	fs,
+	future::Future,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime},
+};
 
 use anyhow::Context;
 use clap::Parser;
 use moq_native::quic;
-use moq_transfork::{Announced, AnnouncedProducer, Error, Filter, Router, RouterConsumer, RouterProducer};
+use moq_transfork::{
+	Announced, AnnouncedConsumer, AnnouncedProducer, Dropped, Error, Filter, GroupConsumer, GroupProducer, Retention,
+	Router, RouterConsumer, RouterProducer, Session, Track, TrackConsumer, TrackEvent, TrackProducer,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
 use tracing::Instrument;
 use url::Url;
 
-use crate::Origins;
+use crate::{Conflict, Health, Metrics, Origins, Permissions, Recorder, SessionInfo, Sessions, INTERNAL_PREFIX};
+
+// How long to wait for a failed origin to be replaced by a standby before giving up.
+const FAILOVER_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Nodes announce themselves under this prefix so others can discover them.
+const ORIGINS: &str = "internal/origins/";
+
+// A node was discovered or forgotten by a root, a static peer, or our own clients.
+enum Discovery {
+	Active(String),
+	Ended(String),
+}
 
 #[derive(Clone, Parser)]
 pub struct ClusterConfig {
 	/// Announce our tracks and discover other origins via this server.
-	/// If not provided, then clustering is disabled.
+	/// Can be provided multiple times for redundancy, in which case we connect to every root.
+	/// If not provided, then clustering is disabled unless there are static peers.
 	///
 	/// Peers will connect to use via this hostname.
 	#[arg(long)]
-	pub cluster_root: Option<String>,
+	pub cluster_root: Vec<String>,
+
+	/// Always connect to this node, in addition to any discovered via the roots.
+	/// Can be provided multiple times, ex. for a static full-mesh cluster without a root.
+	#[arg(long)]
+	pub cluster_peer: Vec<String>,
 
 	/// Our unique name which we advertise to other origins.
 	/// If not provided, then we are a read-only member of the cluster.
@@ -24,6 +53,18 @@ pub struct ClusterConfig {
 	/// Peers will connect to use via this hostname.
 	#[arg(long)]
 	pub cluster_node: Option<String>,
+
+	/// Present the JWT in this file when connecting to other nodes.
+	/// Required if the other nodes have authentication enabled; it should grant publish and subscribe to everything.
+	/// If authentication is disabled, then any shared secret works, and only sessions presenting it are treated as cluster nodes.
+	#[arg(long)]
+	pub cluster_token: Option<PathBuf>,
+
+	/// What to do when multiple origins announce the same path.
+	/// Standby origins take over automatically when the active origin unannounces.
+	/// Any origin that isn't active is told with a duplicate error, without closing its session.
+	#[arg(long, value_enum, default_value_t)]
+	pub announce_conflict: Conflict,
 }
 
 #[derive(Clone)]
@@ -39,134 +80,533 @@ pub struct Cluster {
 
 	// Used to route incoming requests to the origins above.
 	pub router: RouterConsumer,
+
+	// How many older groups to keep for each track we relay.
+	pub retention: Retention,
+
+	// Tracks we're currently relaying, deduplicated by path.
+	upstreams: Arc<Mutex<HashMap<String, TrackProducer>>>,
+
+	// Every active session, both clients and remote nodes.
+	pub sessions: Sessions,
+
+	pub metrics: Metrics,
+
+	// The connection state of each remote node.
+	pub health: Health,
+
+	// The connection state of each root node.
+	pub roots: Health,
+
+	// Records matching tracks to disk, and replays them once the origin is gone.
+	pub recorder: Recorder,
 }
 
 impl Cluster {
-	pub fn new(config: ClusterConfig, client: quic::Client) -> Self {
+	pub fn new(
+		config: ClusterConfig,
+		client: quic::Client,
+		retention: Retention,
+		metrics: Metrics,
+		recorder: Recorder,
+	) -> Self {
 		let (producer, consumer) = Router { capacity: 1024 }.produce();
 
 		let this = Cluster {
+			locals: Origins::new(config.announce_conflict),
+			remotes: Origins::new(config.announce_conflict),
 			config,
 			client,
 			router: consumer,
-			locals: Origins::new(),
-			remotes: Origins::new(),
+			retention,
+			upstreams: Default::default(),
+			sessions: Sessions::default(),
+			metrics,
+			health: Health::default(),
+			roots: Health::default(),
+			recorder,
 		};
 
-		tokio::spawn(this.clone().run_router(producer).in_current_span());
+		tokio::spawn(this.clone().run_router(producer, Permissions::full()).in_current_span());
+		tokio::spawn(this.clone().run_recorder().in_current_span());
 
 		this
 	}
 
+	/// Returns a router that only serves the paths a session is allowed to subscribe to.
+	pub fn route_for(&self, permissions: Permissions) -> RouterConsumer {
+		let (producer, consumer) = Router { capacity: 1024 }.produce();
+		tokio::spawn(self.clone().run_router(producer, permissions).in_current_span());
+		consumer
+	}
+
 	// This is the GUTS of the entire relay.
 	// We route any incoming track requests to the appropriate session.
-	async fn run_router(self, mut router: RouterProducer) {
+	async fn run_router(self, mut router: RouterProducer, permissions: Permissions) {
 		while let Some(req) = router.requested().await {
-			let origin = if let Some(origin) = self.locals.route(&req.track.path).clone() {
-				origin
-			} else if let Some(origin) = self.remotes.route(&req.track.path).clone() {
-				origin
-			} else {
-				req.close(Error::NotFound);
+			if !permissions.can_subscribe(&req.track.path) {
+				tracing::warn!(path = %req.track.path, "unauthorized subscribe");
+				self.metrics.subscribes_total.with_label_values(&["unauthorized"]).inc();
+				req.close(Error::Unauthorized);
+				continue;
+			}
+
+			if let Some(track) = self.relay(&req.track) {
+				self.metrics.subscribes_total.with_label_values(&["ok"]).inc();
+				req.serve(track);
 				continue;
+			}
+
+			// There's no live origin, so fall back to a recording if there is one.
+			// NOTE: This reads from disk, so it's done in a background task to avoid blocking other requests.
+			let this = self.clone();
+
+			tokio::spawn(
+				async move {
+					match this.recorder.replay(req.track.clone()).await {
+						Some(Ok(track)) => {
+							this.metrics.subscribes_total.with_label_values(&["recorded"]).inc();
+							req.serve(track);
+						}
+						Some(Err(err)) => {
+							tracing::warn!(?err, path = %req.track.path, "failed to replay recording");
+							this.metrics.subscribes_total.with_label_values(&["not_found"]).inc();
+							req.close(Error::NotFound);
+						}
+						None => {
+							this.metrics.subscribes_total.with_label_values(&["not_found"]).inc();
+							req.close(Error::NotFound);
+						}
+					}
+				}
+				.in_current_span(),
+			);
+		}
+	}
+
+	// Subscribe to a track from its live origin, returning None if it's not announced.
+	fn relay(&self, track: &Track) -> Option<TrackConsumer> {
+		let mut upstreams = self.upstreams.lock().unwrap();
+
+		// Share the upstream subscription if we're already relaying the track.
+		if let Some(upstream) = upstreams.get(&track.path) {
+			return Some(upstream.subscribe());
+		}
+
+		let origin = self.route(&track.path)?;
+
+		let (producer, consumer) = track.clone().produce();
+		producer.set_retention(self.retention);
+		upstreams.insert(track.path.clone(), producer.clone());
+
+		let this = self.clone();
+		let path = track.path.clone();
+
+		tokio::spawn(
+			async move {
+				this.run_upstream(producer, origin).await;
+				this.upstreams.lock().unwrap().remove(&path);
+			}
+			.in_current_span(),
+		);
+
+		Some(consumer)
+	}
+
+	// Record any announced tracks that match the configured patterns.
+	async fn run_recorder(self) {
+		if !self.recorder.enabled() {
+			return;
+		}
+
+		let recorder = self.recorder.clone();
+		let visible = move |path: &str| recorder.matches(path) && !path.starts_with(INTERNAL_PREFIX);
+
+		let mut announced = self.announced(Filter::Any, visible, std::future::pending());
+		let mut recording: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+		while let Some(announce) = announced.next().await {
+			match announce {
+				Announced::Active(am) => {
+					let path = am.full().to_string();
+
+					// NOTE: Subscribe to the live origin only, otherwise we could record our own recording.
+					let track = match self.relay(&Track::new(&path)) {
+						Some(track) => track,
+						None => continue,
+					};
+
+					let recorder = self.recorder.clone();
+					let handle = tokio::spawn(
+						async move {
+							if let Err(err) = recorder.record(track).await {
+								tracing::warn!(?err, "recording failed");
+							}
+						}
+						.in_current_span(),
+					);
+
+					if let Some(prev) = recording.insert(path, handle) {
+						prev.abort();
+					}
+				}
+				Announced::Ended(am) => {
+					if let Some(handle) = recording.remove(am.full()) {
+						handle.abort();
+					}
+				}
+				Announced::Live => {}
+			}
+		}
+	}
+
+	/// Returns both local and remote announcements matching the filter, limited to the paths that are visible.
+	///
+	/// Announcements are forwarded until `until` resolves or all of the consumers are dropped.
+	pub fn announced<V, U>(&self, filter: Filter, visible: V, until: U) -> AnnouncedConsumer
+	where
+		V: Fn(&str) -> bool + Send + 'static,
+		U: Future<Output = ()> + Send + 'static,
+	{
+		let mut producer = AnnouncedProducer::new();
+		let consumer = producer.subscribe(Filter::Any);
+
+		let mut locals = self.locals.announced(filter.clone());
+		let mut remotes = self.remotes.announced(filter);
+
+		tokio::spawn(async move {
+			tokio::pin!(until);
+
+			// The same path can be announced by both locals and remotes, so count them.
+			let mut active: HashMap<String, usize> = HashMap::new();
+			let mut live = 0;
+
+			loop {
+				let announced = tokio::select! {
+					Some(announced) = locals.next() => announced,
+					Some(announced) = remotes.next() => announced,
+					_ = &mut until => return,
+					_ = producer.closed() => return,
+				};
+
+				match announced {
+					Announced::Active(am) if visible(am.full()) => {
+						let count = active.entry(am.full().to_string()).or_default();
+						*count += 1;
+
+						if *count == 1 {
+							producer.announce(am.full());
+						}
+					}
+					Announced::Ended(am) if visible(am.full()) => {
+						if let hash_map::Entry::Occupied(mut entry) = active.entry(am.full().to_string()) {
+							*entry.get_mut() -= 1;
+
+							if *entry.get() == 0 {
+								entry.remove();
+								producer.unannounce(am.full());
+							}
+						}
+					}
+					Announced::Live => {
+						// We're only live once both locals and remotes are.
+						live += 1;
+						if live == 2 {
+							producer.live();
+						}
+					}
+					_ => {
+						// Not visible, so don't advertise it.
+					}
+				}
+			}
+		});
+
+		consumer
+	}
+
+	// Returns the active origin for a path, preferring local origins over remote ones.
+	fn route(&self, path: &str) -> Option<Session> {
+		self.locals.route(path).or_else(|| self.remotes.route(path))
+	}
+
+	// Relay a track from the active origin, failing over to a standby origin if it goes away.
+	// Subscribers stay attached to our copy of the track so they don't notice the switch.
+	#[tracing::instrument("upstream", skip_all, fields(path = %track.path))]
+	async fn run_upstream(&self, mut track: TrackProducer, mut origin: Session) {
+		// The last sequence we relayed, so the next origin continues from there.
+		let mut last: Option<u64> = None;
+
+		loop {
+			let mut upstream = origin.subscribe(track.info.as_ref().clone());
+
+			// Added to each sequence from this origin, in case it's behind the previous origin.
+			let mut offset = None;
+
+			let res = loop {
+				let event = tokio::select! {
+					res = upstream.next() => res,
+					_ = track.unused() => return,
+				};
+
+				let group = match event {
+					Ok(Some(TrackEvent::Group(group))) => group,
+					Ok(Some(TrackEvent::Dropped(dropped))) => {
+						// Forward drops so downstream subscribers can conceal the gap immediately.
+						track.drop_groups(Dropped {
+							sequence: dropped.sequence + offset.unwrap_or_default(),
+							..dropped
+						});
+						continue;
+					}
+					Ok(None) => break Ok(()),
+					Err(err) => break Err(err),
+				};
+
+				// NOTE: Redundant origins may not agree on sequence numbers, so we continue at the next group.
+				let offset = *offset.get_or_insert(match last {
+					Some(last) if group.sequence <= last => last + 1 - group.sequence,
+					_ => 0,
+				});
+
+				let sequence = group.sequence + offset;
+				last = last.max(Some(sequence));
+
+				// Keep sending tiny groups as datagrams if that's how the origin sent them.
+				track.set_delivery(upstream.delivery());
+
+				// Expire groups when the origin would, so stale groups aren't served from our cache.
+				track.set_ttl(upstream.ttl());
+
+				tokio::spawn(Self::relay_group(group, track.create_group(sequence)).in_current_span());
 			};
 
-			let track = origin.subscribe(req.track.clone());
-			req.serve(track)
+			origin = match self.failover(&track.path, &origin).await {
+				Some(next) => next,
+				None => {
+					tracing::info!(?res, "upstream ended");
+
+					if let Err(err) = res {
+						track.close(err);
+					}
+
+					return;
+				}
+			};
+
+			tracing::info!(?last, "failing over to standby origin");
+		}
+	}
+
+	// Wait for the path to be routed to a different origin, returning None if there's no other origin.
+	async fn failover(&self, path: &str, failed: &Session) -> Option<Session> {
+		let deadline = tokio::time::Instant::now() + FAILOVER_TIMEOUT;
+
+		// The failed origin stays routable until it unannounces, which may take a moment.
+		loop {
+			match self.route(path) {
+				Some(origin) if &origin != failed => return Some(origin),
+				Some(_) if tokio::time::Instant::now() < deadline => {
+					tokio::time::sleep(Duration::from_millis(100)).await
+				}
+				_ => return None,
+			}
+		}
+	}
+
+	// Copy a group from an origin to our copy of the track.
+	async fn relay_group(mut upstream: GroupConsumer, mut downstream: GroupProducer) {
+		let res = async {
+			while let Some(mut frame) = upstream.next_frame().await? {
+				let mut copy = downstream.create_frame(frame.size);
+				while let Some(chunk) = frame.read().await? {
+					copy.write(chunk);
+				}
+			}
+
+			Ok::<_, Error>(())
+		}
+		.await;
+
+		if let Err(err) = res {
+			downstream.close(err);
 		}
 	}
 
 	pub async fn run(self) -> anyhow::Result<()> {
-		let root = self.config.cluster_root.clone();
-		let node = self.config.cluster_node.as_ref().map(|node| node.to_string());
+		let roots = self.config.cluster_root.clone();
+		let node = self.config.cluster_node.clone();
 
-		tracing::info!(?root, ?node, "initializing cluster");
+		tracing::info!(?roots, ?node, peers = ?self.config.cluster_peer, "initializing cluster");
 
 		// If we're a node, then we need to announce ourselves as an origin.
 		let mut myself = AnnouncedProducer::new();
 		if let Some(node) = self.config.cluster_node.as_ref() {
-			let origin = format!("internal/origins/{}", node);
+			let origin = format!("{}{}", ORIGINS, node);
 			myself.announce(origin);
 		}
 
-		let filter = Filter::Prefix("internal/origins/".into());
-
-		// If we're using a root node, then we have to connect to it.
-		let mut announced = match root.as_ref() {
-			Some(root) if Some(root) != node.as_ref() => {
-				tracing::info!(?root, "connecting to root");
-
-				// Connect to the root node.
-				let root = Url::parse(&format!("https://{}", root)).context("invalid root URL")?;
-				let root = self.client.connect(root).await.context("failed to connect to root")?;
+		// Every source of discovered nodes feeds into this channel.
+		let (discovered, mut discovery) = mpsc::unbounded_channel();
 
-				let mut root = moq_transfork::Session::connect(root)
-					.await
-					.context("failed to establish root session")?;
+		// Connect to every root (except ourselves) so losing any one of them doesn't partition the cluster.
+		for root in roots.iter().filter(|root| Some(*root) != node.as_ref()) {
+			let this = self.clone();
+			let root = root.clone();
+			let myself = myself.clone();
+			let discovered = discovered.clone();
 
-				// Announce ourselves as an origin to the root node.
-				root.announce(myself.subscribe(Filter::Any));
+			tokio::spawn(async move { this.run_root_retry(&root, myself, discovered).await }.in_current_span());
+		}
 
-				// Subscribe to available origins.
-				root.announced(filter)
-			}
-			// Otherwise, we're the root node but we still want to connect to other nodes.
-			_ => {
-				// Announce ourselves as an origin to all connected clients.
-				// Technically, we should only announce to cluster clients (not end users), but who cares.
-				let mut locals = self.locals.clone();
-				tokio::spawn(async move {
-					// Run this in a background task so we don't block the main loop.
-					// (it will never exit)
-					locals.announce(myself.subscribe(Filter::Any), None).await
-				});
+		// If we're a root node (or there are none), then other nodes announce themselves to us.
+		if roots.is_empty() || node.as_ref().is_some_and(|node| roots.contains(node)) {
+			// Announce ourselves as an origin to all connected cluster nodes.
+			// NOTE: End users can't see this because internal paths require the cluster role.
+			let mut locals = self.locals.clone();
+			let announced = myself.subscribe(Filter::Any);
+			tokio::spawn(async move {
+				// Run this in a background task so we don't block the main loop.
+				// (it will never exit)
+				locals.announce(announced, None, Permissions::full(), None).await
+			});
+
+			let mut announced = self.locals.announced(Filter::Prefix(ORIGINS.into()));
+			let discovered = discovered.clone();
+
+			tokio::spawn(async move {
+				while let Some(announce) = announced.next().await {
+					let discovery = match announce {
+						Announced::Active(am) => Discovery::Active(am.to_capture()),
+						Announced::Ended(am) => Discovery::Ended(am.to_capture()),
+						Announced::Live => continue,
+					};
+
+					discovered.send(discovery).ok();
+				}
+			});
+		}
 
-				// Subscribe to the available origins.
-				self.locals.announced(filter)
-			}
-		};
+		// Static peers are always connected, regardless of what the roots say.
+		for peer in &self.config.cluster_peer {
+			discovered.send(Discovery::Active(peer.clone())).ok();
+		}
 
-		// Keep track of the active remotes.
-		let mut remotes = HashMap::new();
+		// Keep track of the active remotes, and how many sources know about each.
+		let mut remotes: HashMap<String, (usize, JoinHandle<()>)> = HashMap::new();
 
 		// Discover other origins.
 		// NOTE: The root node will connect to all other nodes as a client, ignoring the existing (server) connection.
 		// This ensures that nodes are advertising a valid hostname before any tracks get announced.
-		while let Some(announce) = announced.next().await {
-			match announce {
-				Announced::Active(am) => {
-					let host = am.to_capture();
+		while let Some(discovery) = discovery.recv().await {
+			match discovery {
+				Discovery::Active(host) => {
 					if Some(&host) == node.as_ref() {
 						// Skip ourselves.
 						continue;
 					}
 
+					if let Some((sources, _)) = remotes.get_mut(&host) {
+						*sources += 1;
+						continue;
+					}
+
 					tracing::info!(?host, "discovered origin");
 
 					let mut this = self.clone();
 					let remote = host.clone();
 
-					let handle = tokio::spawn(
-						async move {
-							loop {
-								if let Err(err) = this.run_remote(&remote).await {
-									tracing::error!(?err, "remote error, retrying");
-								}
+					let handle = tokio::spawn(async move { this.run_remote_retry(&remote).await }.in_current_span());
 
-								// TODO smarter backoff
-								tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-							}
-						}
-						.in_current_span(),
-					);
+					remotes.insert(host, (1, handle));
+				}
+				Discovery::Ended(host) => {
+					let sources = match remotes.get_mut(&host) {
+						Some((sources, _)) => sources,
+						None => continue,
+					};
+
+					*sources -= 1;
+					if *sources > 0 {
+						continue;
+					}
 
-					remotes.insert(host, handle);
+					if let Some((_, handle)) = remotes.remove(&host) {
+						tracing::warn!(?host, "terminating remote");
+						handle.abort();
+
+						self.metrics.remote_connected.remove_label_values(&[&host]).ok();
+						self.health.remove(&host);
+					}
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	// Keep reconnecting to a root node with backoff, forwarding any origins it knows about.
+	#[tracing::instrument("root", skip_all, fields(%root))]
+	async fn run_root_retry(
+		&self,
+		root: &str,
+		myself: AnnouncedProducer,
+		discovered: mpsc::UnboundedSender<Discovery>,
+	) {
+		self.roots.connecting(root);
+
+		loop {
+			let mut hosts = HashSet::new();
+
+			let err = match self.run_root(root, &myself, &discovered, &mut hosts).await {
+				Ok(()) => anyhow::anyhow!("disconnected"),
+				Err(err) => err,
+			};
+
+			// Forget anything this root told us about; other roots or peers may still know about them.
+			for host in hosts {
+				discovered.send(Discovery::Ended(host)).ok();
+			}
+
+			let delay = self.roots.failed(root, &err);
+			tokio::time::sleep(delay).await;
+		}
+	}
+
+	async fn run_root(
+		&self,
+		root: &str,
+		myself: &AnnouncedProducer,
+		discovered: &mpsc::UnboundedSender<Discovery>,
+		hosts: &mut HashSet<String>,
+	) -> anyhow::Result<()> {
+		let url = self.node_url(root).context("invalid root URL")?;
+		let conn = self.client.connect(url).await.context("failed to connect to root")?;
+
+		let mut session = quic::moq_connect(conn)
+			.await
+			.context("failed to establish root session")?;
+
+		self.roots.up(root);
+
+		// Announce ourselves as an origin to the root node.
+		session.announce(myself.subscribe(Filter::Any));
+
+		// Subscribe to available origins.
+		let mut announced = session.announced(Filter::Prefix(ORIGINS.into()));
+
+		while let Some(announce) = announced.next().await {
+			match announce {
+				Announced::Active(am) => {
+					let host = am.to_capture();
+					if hosts.insert(host.clone()) {
+						discovered.send(Discovery::Active(host)).ok();
+					}
 				}
 				Announced::Ended(am) => {
 					let host = am.to_capture();
-					if let Some(handle) = remotes.remove(&host) {
-						tracing::warn!(?host, "terminating remote");
-						handle.abort();
+					if hosts.remove(&host) {
+						discovered.send(Discovery::Ended(host)).ok();
 					}
 				}
 				Announced::Live => {
@@ -178,18 +618,51 @@ impl Cluster {
 		Ok(())
 	}
 
-	#[tracing::instrument("remote", skip_all, err, fields(%host))]
+	// Keep reconnecting to a remote node with backoff, until it's no longer announced.
+	#[tracing::instrument("remote", skip_all, fields(%host))]
+	async fn run_remote_retry(&mut self, host: &str) {
+		self.health.connecting(host);
+
+		loop {
+			let err = match self.run_remote(host).await {
+				Ok(()) => anyhow::anyhow!("disconnected"),
+				Err(err) => err,
+			};
+
+			self.metrics.remote_errors.with_label_values(&[host]).inc();
+
+			let delay = self.health.failed(host, &err);
+			tokio::time::sleep(delay).await;
+		}
+	}
+
+	// NOTE: Errors are logged by Health to avoid spamming when a node is down.
 	async fn run_remote(&mut self, host: &str) -> anyhow::Result<()> {
-		let url = Url::parse(&format!("https://{}", host)).context("invalid node URL")?;
+		let url = self.node_url(host).context("invalid node URL")?;
 
 		// Connect to the remote node.
 		let conn = self.client.connect(url).await.context("failed to connect to remote")?;
 
-		let mut session = moq_transfork::Session::connect(conn)
-			.await
-			.context("failed to establish session")?;
+		let mut session = quic::moq_connect(conn).await.context("failed to establish session")?;
+
+		self.health.up(host);
+
+		let _registered = self.sessions.register(SessionInfo {
+			id: self.sessions.next_id(),
+			addr: None,
+			connected: SystemTime::now(),
+			node: Some(host.to_string()),
+			session: session.clone(),
+			router: None,
+		});
+
+		self.metrics.sessions_total.with_label_values(&["remote"]).inc();
+
+		let connected = self.metrics.remote_connected.with_label_values(&[host]);
+		connected.set(1);
 
 		session.route(self.router.clone());
+		session.set_observer(Arc::new(self.metrics.clone()));
 
 		// NOTE: We only announce local tracks to remote nodes.
 		// Otherwise there would be conflicts and we wouldn't know which node is the origin.
@@ -197,8 +670,24 @@ impl Cluster {
 
 		// Add any tracks to the list of remotes for routing.
 		let all = session.announced(Filter::Any);
-		self.remotes.announce(all, Some(session.clone())).await;
+		self.remotes
+			.announce(all, Some(session.clone()), Permissions::full(), None)
+			.await;
+
+		connected.set(0);
 
 		Ok(())
 	}
+
+	// The URL used to connect to another node, including our token if configured.
+	fn node_url(&self, host: &str) -> anyhow::Result<Url> {
+		let mut url = Url::parse(&format!("https://{}", host))?;
+
+		if let Some(path) = &self.config.cluster_token {
+			let token = fs::read_to_string(path).context("failed to read cluster token")?;
+			url.query_pairs_mut().append_pair("jwt", token.trim());
+		}
+
+		Ok(url)
+	}
 }
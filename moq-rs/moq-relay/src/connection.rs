@@ -1,32 +1,121 @@
-use moq_transfork::Filter;
+use std::{net, sync::Arc, time::SystemTime};
 
-use crate::Cluster;
+use moq_native::quic;
+use moq_transfork::{Error, Filter, Observer};
+use url::Url;
+
+use crate::{Auth, Bitrate, Cluster, Limits, SessionInfo};
 
 pub struct Connection {
 	id: u64,
-	session: web_transport::Session,
+	session: web_transport::quinn::Session,
+	addr: net::SocketAddr,
+	url: Option<Url>,
 	cluster: Cluster,
+	auth: Auth,
+	limits: Limits,
 }
 
 impl Connection {
-	pub fn new(id: u64, session: web_transport::Session, cluster: Cluster) -> Self {
-		Self { id, session, cluster }
+	pub fn new(
+		id: u64,
+		session: web_transport::quinn::Session,
+		addr: net::SocketAddr,
+		url: Option<Url>,
+		cluster: Cluster,
+		auth: Auth,
+		limits: Limits,
+	) -> Self {
+		Self {
+			id,
+			session,
+			addr,
+			url,
+			cluster,
+			auth,
+			limits,
+		}
 	}
 
 	#[tracing::instrument("session", skip_all, err, fields(id = self.id))]
-	pub async fn run(mut self) -> anyhow::Result<()> {
-		let mut session = moq_transfork::Session::accept(self.session).await?;
+	pub async fn run(self) -> anyhow::Result<()> {
+		let permissions = match self.auth.verify(self.url.as_ref()) {
+			Ok(permissions) => permissions,
+			Err(err) => {
+				let mut session: web_transport::Session = self.session.into();
+				session.close(Error::Unauthorized.to_code(), &err.to_string());
+				return Err(err);
+			}
+		};
+
+		let mut session = quic::moq_accept(self.session).await?;
+
+		// NOTE: The router is registered with the session so its queue is included in the metrics.
+		let router = self.cluster.route_for(permissions.clone());
+
+		// Make the session visible to the admin API until it's closed.
+		let _registered = self.cluster.sessions.register(SessionInfo {
+			id: self.id,
+			addr: Some(self.addr),
+			connected: SystemTime::now(),
+			node: None,
+			session: session.clone(),
+			router: Some(router.clone()),
+		});
+
+		self.cluster.metrics.sessions_total.with_label_values(&["client"]).inc();
+
+		let metrics: Arc<dyn Observer> = Arc::new(self.cluster.metrics.clone());
+		let bitrate = self
+			.limits
+			.bitrate
+			.map(|limit| Arc::new(Bitrate::new(limit, metrics.clone())));
 
-		// Route any subscriptions to the cluster
-		session.route(self.cluster.router);
+		match bitrate.clone() {
+			Some(bitrate) => session.set_observer(bitrate),
+			None => session.set_observer(metrics),
+		}
 
-		// TODO things will get weird if locals and remotes announce the same path.
-		session.announce(self.cluster.locals.announced(Filter::Any));
-		session.announce(self.cluster.remotes.announced(Filter::Any));
+		if let Some(max) = self.limits.subscriptions {
+			session.set_max_subscriptions(max);
+		}
+
+		// Route any subscriptions to the cluster, as long as they're allowed.
+		session.route(router);
+
+		// Only announce the paths the session is allowed to subscribe to, hiding internal paths from end users.
+		let visible = permissions.clone();
+		let closed = session.clone();
+		session.announce(
+			self.cluster
+				.announced(Filter::Any, move |path| visible.can_subscribe(path), async move {
+					closed.closed().await;
+				}),
+		);
 
 		// Add any announcements to the cluster, indicating we're the origin.
 		let all = session.announced(Filter::Any);
-		self.cluster.locals.announce(all, Some(session.clone())).await;
+		let mut locals = self.cluster.locals.clone();
+		let announce = locals.announce(all, Some(session.clone()), permissions, self.limits.announces);
+		tokio::pin!(announce);
+
+		let exceeded = async {
+			match &bitrate {
+				Some(bitrate) => bitrate.exceeded().await,
+				None => std::future::pending().await,
+			}
+		};
+
+		tokio::select! {
+			_ = &mut announce => return Ok(()),
+			_ = exceeded => {
+				tracing::warn!(limit = ?self.limits.bitrate, "bitrate exceeded");
+				session.clone().close(Error::LimitExceeded);
+			}
+		}
+
+		// Wait for the announcements to be cleaned up.
+		announce.await;
 
 		Ok(())
 	}
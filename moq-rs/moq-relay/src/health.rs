@@ -0,0 +1,241 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime},
+};
+
+use rand::Rng;
+use serde::Serialize;
+
+// The delay before the first retry, doubled after each consecutive failure.
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+// After this many consecutive failures the circuit opens and we only retry occasionally.
+const CIRCUIT_FAILURES: u32 = 5;
+const CIRCUIT_RETRY: Duration = Duration::from_secs(60);
+
+/// The state of our connection to a remote cluster node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+	/// Connecting for the first time.
+	Connecting,
+
+	/// Connected and relaying.
+	Up,
+
+	/// Recently failed, retrying with exponential backoff.
+	Degraded,
+
+	/// Failed repeatedly, so the circuit is open and we only retry occasionally.
+	Down,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RemoteHealth {
+	pub state: State,
+
+	/// Consecutive failures since the last successful connection.
+	pub failures: u32,
+
+	pub last_error: Option<String>,
+
+	/// Seconds since the UNIX epoch when the state last changed.
+	pub since: u64,
+}
+
+/// The health of each remote cluster node, served via `GET /cluster`.
+#[derive(Clone, Default)]
+pub struct Health {
+	remotes: Arc<Mutex<HashMap<String, RemoteHealth>>>,
+}
+
+impl Health {
+	pub fn connecting(&self, host: &str) {
+		self.remotes
+			.lock()
+			.unwrap()
+			.entry(host.to_string())
+			.or_insert_with(|| RemoteHealth {
+				state: State::Connecting,
+				failures: 0,
+				last_error: None,
+				since: now(),
+			});
+	}
+
+	pub fn up(&self, host: &str) {
+		let mut remotes = self.remotes.lock().unwrap();
+		let remote = remotes.entry(host.to_string()).or_insert_with(|| RemoteHealth {
+			state: State::Up,
+			failures: 0,
+			last_error: None,
+			since: now(),
+		});
+
+		if remote.failures > 0 {
+			tracing::info!(host, failures = remote.failures, "remote recovered");
+		} else {
+			tracing::info!(host, "remote up");
+		}
+
+		remote.state = State::Up;
+		remote.failures = 0;
+		remote.since = now();
+	}
+
+	/// Record a failure, returning how long to wait before reconnecting.
+	///
+	/// Only state changes are logged at warn level, so a dead node doesn't spam the logs.
+	pub fn failed(&self, host: &str, err: &anyhow::Error) -> Duration {
+		let mut remotes = self.remotes.lock().unwrap();
+		let remote = remotes.entry(host.to_string()).or_insert_with(|| RemoteHealth {
+			state: State::Connecting,
+			failures: 0,
+			last_error: None,
+			since: now(),
+		});
+
+		remote.failures += 1;
+		remote.last_error = Some(format!("{:#}", err));
+
+		let state = match remote.failures < CIRCUIT_FAILURES {
+			true => State::Degraded,
+			false => State::Down,
+		};
+
+		let delay = match state {
+			State::Down => CIRCUIT_RETRY,
+			_ => backoff(remote.failures),
+		};
+
+		// Spread out retries so nodes don't reconnect in lockstep.
+		let delay = delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+
+		if state != remote.state {
+			match state {
+				State::Down => tracing::warn!(host, failures = remote.failures, ?err, "remote down, circuit open"),
+				_ => tracing::warn!(host, ?err, ?delay, "remote degraded, retrying"),
+			}
+
+			remote.state = state;
+			remote.since = now();
+		} else {
+			tracing::debug!(
+				host,
+				failures = remote.failures,
+				?err,
+				?delay,
+				"remote failed, retrying"
+			);
+		}
+
+		delay
+	}
+
+	pub fn remove(&self, host: &str) {
+		self.remotes.lock().unwrap().remove(host);
+	}
+
+	pub fn list(&self) -> HashMap<String, RemoteHealth> {
+		self.remotes.lock().unwrap().clone()
+	}
+}
+
+// The delay after this many consecutive failures, before any jitter.
+fn backoff(failures: u32) -> Duration {
+	let factor = 1u32.checked_shl(failures.saturating_sub(1)).unwrap_or(u32::MAX);
+	BACKOFF_MIN.saturating_mul(factor).min(BACKOFF_MAX)
+}
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.map(|since| since.as_secs())
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn err() -> anyhow::Error {
+		anyhow::anyhow!("connection refused")
+	}
+
+	// The delay is jittered between half and all of the expected delay.
+	fn assert_jittered(delay: Duration, expected: Duration) {
+		assert!(
+			delay >= expected / 2 && delay <= expected,
+			"{:?} not within {:?}",
+			delay,
+			expected
+		);
+	}
+
+	#[test]
+	fn doubling() {
+		let expected = [1, 2, 4, 8, 16, 30, 30];
+		for (failures, secs) in (1..).zip(expected) {
+			assert_eq!(backoff(failures), Duration::from_secs(secs));
+		}
+
+		assert_eq!(backoff(u32::MAX), BACKOFF_MAX);
+	}
+
+	#[test]
+	fn circuit() {
+		let health = Health::default();
+		health.connecting("node1");
+		assert_eq!(health.list()["node1"].state, State::Connecting);
+
+		for failures in 1..CIRCUIT_FAILURES {
+			let delay = health.failed("node1", &err());
+			assert_jittered(delay, backoff(failures));
+
+			let remote = &health.list()["node1"];
+			assert_eq!(remote.state, State::Degraded);
+			assert_eq!(remote.failures, failures);
+			assert_eq!(remote.last_error.as_deref(), Some("connection refused"));
+		}
+
+		// The circuit opens, and stays open until we connect.
+		for failures in CIRCUIT_FAILURES..CIRCUIT_FAILURES + 3 {
+			let delay = health.failed("node1", &err());
+			assert_jittered(delay, CIRCUIT_RETRY);
+
+			let remote = &health.list()["node1"];
+			assert_eq!(remote.state, State::Down);
+			assert_eq!(remote.failures, failures);
+		}
+	}
+
+	#[test]
+	fn up() {
+		let health = Health::default();
+		health.connecting("node1");
+
+		for _ in 0..CIRCUIT_FAILURES {
+			health.failed("node1", &err());
+		}
+		assert_eq!(health.list()["node1"].state, State::Down);
+
+		health.up("node1");
+		let remote = &health.list()["node1"];
+		assert_eq!(remote.state, State::Up);
+		assert_eq!(remote.failures, 0);
+
+		// The next failure starts the backoff from scratch.
+		let delay = health.failed("node1", &err());
+		assert_jittered(delay, BACKOFF_MIN);
+		assert_eq!(health.list()["node1"].state, State::Degraded);
+
+		// Connecting again doesn't reset the state.
+		health.connecting("node1");
+		assert_eq!(health.list()["node1"].state, State::Degraded);
+
+		health.remove("node1");
+		assert!(health.list().is_empty());
+	}
+}
@@ -0,0 +1,245 @@
+use std::{
+	collections::HashMap,
+	net,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use clap::Parser;
+use moq_transfork::Observer;
+use tokio::{sync::Notify, time::Instant};
+
+// The window used to count new connections per IP.
+const CONNECTION_WINDOW: Duration = Duration::from_secs(60);
+
+// How long a publisher can exceed its bitrate before it's disconnected.
+const BITRATE_BURST: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Parser)]
+pub struct LimitsConfig {
+	/// The maximum number of concurrent subscriptions per session.
+	#[arg(long)]
+	pub limit_subscriptions: Option<usize>,
+
+	/// The maximum number of paths each session can announce.
+	#[arg(long)]
+	pub limit_announces: Option<usize>,
+
+	/// The maximum inbound bitrate per session, in bits per second.
+	#[arg(long)]
+	pub limit_bitrate: Option<u64>,
+
+	/// The maximum number of new connections per IP address, per minute.
+	#[arg(long)]
+	pub limit_connections: Option<usize>,
+}
+
+/// Usage limits, returning [moq_transfork::Error::LimitExceeded] to the offending peer.
+#[derive(Clone)]
+pub struct Limits {
+	pub subscriptions: Option<usize>,
+	pub announces: Option<usize>,
+	pub bitrate: Option<u64>,
+
+	connections: Option<usize>,
+
+	// The start of the current window and the number of connections within it, per IP.
+	recent: Arc<Mutex<HashMap<net::IpAddr, (Instant, usize)>>>,
+}
+
+impl Limits {
+	pub fn new(config: LimitsConfig) -> Self {
+		Self {
+			subscriptions: config.limit_subscriptions,
+			announces: config.limit_announces,
+			bitrate: config.limit_bitrate,
+			connections: config.limit_connections,
+			recent: Default::default(),
+		}
+	}
+
+	/// Returns false if the IP has made too many connections recently.
+	pub fn allow_connection(&self, ip: net::IpAddr) -> bool {
+		let max = match self.connections {
+			Some(max) => max,
+			None => return true,
+		};
+
+		let now = Instant::now();
+		let mut recent = self.recent.lock().unwrap();
+
+		// Forget about any IPs that haven't connected in a while.
+		recent.retain(|_, (start, _)| now.duration_since(*start) < CONNECTION_WINDOW);
+
+		let (_, count) = recent.entry(ip).or_insert((now, 0));
+		*count += 1;
+
+		*count <= max
+	}
+}
+
+/// Wraps another observer, signalling when the inbound bitrate is exceeded.
+///
+/// This is a token bucket that allows bursts of up to [BITRATE_BURST].
+pub struct Bitrate {
+	inner: Arc<dyn Observer>,
+
+	// In bytes per second.
+	rate: f64,
+
+	// The available bytes and when they were last refilled.
+	bucket: Mutex<(f64, Instant)>,
+
+	exceeded: Notify,
+}
+
+impl Bitrate {
+	pub fn new(bits_per_second: u64, inner: Arc<dyn Observer>) -> Self {
+		let rate = bits_per_second as f64 / 8.0;
+
+		Self {
+			inner,
+			rate,
+			bucket: Mutex::new((rate * BITRATE_BURST.as_secs_f64(), Instant::now())),
+			exceeded: Notify::new(),
+		}
+	}
+
+	/// Block until the bitrate has been exceeded.
+	pub async fn exceeded(&self) {
+		self.exceeded.notified().await
+	}
+
+	fn consume(&self, size: usize) {
+		let mut bucket = self.bucket.lock().unwrap();
+		let (available, refilled) = &mut *bucket;
+
+		let now = Instant::now();
+		let capacity = self.rate * BITRATE_BURST.as_secs_f64();
+
+		*available = (*available + now.duration_since(*refilled).as_secs_f64() * self.rate).min(capacity);
+		*refilled = now;
+		*available -= size as f64;
+
+		if *available < 0.0 {
+			// Stores a permit if nobody is waiting yet.
+			self.exceeded.notify_one();
+		}
+	}
+}
+
+impl Observer for Bitrate {
+	fn group_sent(&self, path: &str) {
+		self.inner.group_sent(path)
+	}
+
+	fn frame_sent(&self, path: &str, size: usize) {
+		self.inner.frame_sent(path, size)
+	}
+
+	fn group_received(&self, path: &str) {
+		self.inner.group_received(path)
+	}
+
+	fn frame_received(&self, path: &str, size: usize) {
+		self.consume(size);
+		self.inner.frame_received(path, size)
+	}
+
+	fn drop_sent(&self, path: &str, code: u32) {
+		self.inner.drop_sent(path, code)
+	}
+
+	fn drop_received(&self, path: &str, code: u32) {
+		self.inner.drop_received(path, code)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use futures::FutureExt;
+
+	struct Noop;
+	impl Observer for Noop {}
+
+	fn limits(connections: Option<usize>) -> Limits {
+		Limits::new(LimitsConfig {
+			limit_subscriptions: None,
+			limit_announces: None,
+			limit_bitrate: None,
+			limit_connections: connections,
+		})
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn connections() {
+		let limits = limits(Some(2));
+		let ip: net::IpAddr = "192.0.2.1".parse().unwrap();
+		let other: net::IpAddr = "192.0.2.2".parse().unwrap();
+
+		assert!(limits.allow_connection(ip));
+		assert!(limits.allow_connection(ip));
+		assert!(!limits.allow_connection(ip));
+
+		// Each IP is counted separately.
+		assert!(limits.allow_connection(other));
+
+		// The window starts with the first connection, not the latest.
+		tokio::time::advance(CONNECTION_WINDOW - Duration::from_secs(1)).await;
+		assert!(!limits.allow_connection(ip));
+
+		tokio::time::advance(Duration::from_secs(1)).await;
+		assert!(limits.allow_connection(ip));
+		assert!(limits.allow_connection(ip));
+		assert!(!limits.allow_connection(ip));
+	}
+
+	#[test]
+	fn connections_unlimited() {
+		let limits = limits(None);
+		let ip: net::IpAddr = "192.0.2.1".parse().unwrap();
+
+		for _ in 0..1000 {
+			assert!(limits.allow_connection(ip));
+		}
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn bitrate() {
+		// 1000 bytes per second, so a burst of up to 2000 bytes.
+		let bitrate = Bitrate::new(8000, Arc::new(Noop));
+
+		bitrate.frame_received("station", 1500);
+		bitrate.frame_received("station", 500);
+		assert!(bitrate.exceeded().now_or_never().is_none());
+
+		// The bucket refills at the bitrate.
+		tokio::time::advance(Duration::from_secs(1)).await;
+		bitrate.frame_received("station", 1000);
+		assert!(bitrate.exceeded().now_or_never().is_none());
+
+		// Sent faster than it refills.
+		tokio::time::advance(Duration::from_millis(500)).await;
+		bitrate.frame_received("station", 1000);
+		assert!(bitrate.exceeded().now_or_never().is_some());
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn bitrate_sustained() {
+		let bitrate = Bitrate::new(8000, Arc::new(Noop));
+
+		// Exactly the bitrate is fine forever.
+		for _ in 0..10 {
+			tokio::time::advance(Duration::from_millis(100)).await;
+			bitrate.frame_received("station", 100);
+		}
+		assert!(bitrate.exceeded().now_or_never().is_none());
+
+		// An idle publisher can't save up more than the burst.
+		tokio::time::advance(Duration::from_secs(60)).await;
+		bitrate.frame_received("station", 2001);
+		assert!(bitrate.exceeded().now_or_never().is_some());
+	}
+}
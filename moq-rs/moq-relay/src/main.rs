@@ -1,11 +1,27 @@
+mod admin;
+mod auth;
+mod cache;
 mod cluster;
 mod connection;
+mod health;
+mod limits;
+mod metrics;
 mod origins;
+mod record;
+mod sessions;
 mod web;
 
+pub use admin::*;
+pub use auth::*;
+pub use cache::*;
 pub use cluster::*;
 pub use connection::*;
+pub use health::*;
+pub use limits::*;
+pub use metrics::*;
 pub use origins::*;
+pub use record::*;
+pub use sessions::*;
 pub use web::*;
 
 use anyhow::Context;
@@ -29,6 +45,26 @@ pub struct Config {
 	/// Cluster configuration.
 	#[command(flatten)]
 	pub cluster: ClusterConfig,
+
+	/// Authentication configuration.
+	#[command(flatten)]
+	pub auth: AuthConfig,
+
+	/// Cache configuration.
+	#[command(flatten)]
+	pub cache: CacheConfig,
+
+	/// Metrics configuration.
+	#[command(flatten)]
+	pub metrics: MetricsConfig,
+
+	/// Rate limits and quotas.
+	#[command(flatten)]
+	pub limits: LimitsConfig,
+
+	/// Recording configuration.
+	#[command(flatten)]
+	pub record: RecordConfig,
 }
 
 #[tokio::main]
@@ -50,7 +86,19 @@ async fn main() -> anyhow::Result<()> {
 	let quic = quic::Endpoint::new(quic::Config { bind, tls: tls.clone() })?;
 	let mut server = quic.server.context("missing TLS certificate")?;
 
-	let cluster = Cluster::new(config.cluster.clone(), quic.client);
+	let auth = Auth::new(config.auth.clone(), config.cluster.cluster_token.as_deref())?;
+	let limits = Limits::new(config.limits.clone());
+
+	let metrics = Metrics::new(config.metrics.clone());
+	let recorder = Recorder::new(config.record.clone())?;
+
+	let cluster = Cluster::new(
+		config.cluster.clone(),
+		quic.client,
+		config.cache.retention(),
+		metrics,
+		recorder,
+	);
 	let cloned = cluster.clone();
 	tokio::spawn(async move { cloned.run().await.expect("cluster failed") });
 
@@ -59,6 +107,7 @@ async fn main() -> anyhow::Result<()> {
 		bind,
 		tls,
 		cluster: cluster.clone(),
+		auth: auth.clone(),
 	});
 
 	tokio::spawn(async move {
@@ -67,11 +116,28 @@ async fn main() -> anyhow::Result<()> {
 
 	tracing::info!(addr = %bind, "listening");
 
-	let mut conn_id = 0;
-
 	while let Some(conn) = server.accept().await {
-		let session = Connection::new(conn_id, conn.into(), cluster.clone());
-		conn_id += 1;
+		let id = cluster.sessions.next_id();
+		let addr = conn.session.remote_address();
+
+		if !limits.allow_connection(addr.ip()) {
+			tracing::warn!(%addr, "too many connections");
+
+			let mut session: web_transport::Session = conn.session.into();
+			let err = moq_transfork::Error::LimitExceeded;
+			session.close(err.to_code(), &err.to_string());
+			continue;
+		}
+
+		let session = Connection::new(
+			id,
+			conn.session,
+			addr,
+			conn.url,
+			cluster.clone(),
+			auth.clone(),
+			limits.clone(),
+		);
 
 		tokio::spawn(async move {
 			session.run().await.ok();
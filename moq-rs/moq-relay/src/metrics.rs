@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use clap::Parser;
+use prometheus::{IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+
+use crate::Cluster;
+
+#[derive(Clone, Parser)]
+pub struct MetricsConfig {
+	/// Report traffic metrics per path prefix, using the longest match. Can be provided multiple times.
+	/// Paths without a matching prefix are reported as "other", which keeps the number of series bounded.
+	#[arg(long)]
+	pub metrics_prefix: Vec<String>,
+}
+
+/// Prometheus metrics for the relay, served via `GET /metrics`.
+#[derive(Clone)]
+pub struct Metrics {
+	registry: Registry,
+	prefixes: Arc<Vec<String>>,
+
+	// Updated when scraped.
+	sessions: IntGaugeVec,
+	announced: IntGaugeVec,
+	subscriptions: IntGauge,
+	router_queue: IntGauge,
+
+	pub sessions_total: IntCounterVec,
+	pub subscribes_total: IntCounterVec,
+	pub remote_connected: IntGaugeVec,
+	pub remote_errors: IntCounterVec,
+
+	groups: IntCounterVec,
+	frames: IntCounterVec,
+	bytes: IntCounterVec,
+	drops: IntCounterVec,
+}
+
+impl Metrics {
+	pub fn new(config: MetricsConfig) -> Self {
+		let registry = Registry::new_custom(Some("moq_relay".to_string()), None).expect("invalid registry");
+
+		let gauge = |name: &str, help: &str| {
+			let gauge = IntGauge::new(name, help).unwrap();
+			registry.register(Box::new(gauge.clone())).unwrap();
+			gauge
+		};
+
+		let gauges = |name: &str, help: &str, labels: &[&str]| {
+			let gauges = IntGaugeVec::new(Opts::new(name, help), labels).unwrap();
+			registry.register(Box::new(gauges.clone())).unwrap();
+			gauges
+		};
+
+		let counters = |name: &str, help: &str, labels: &[&str]| {
+			let counters = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+			registry.register(Box::new(counters.clone())).unwrap();
+			counters
+		};
+
+		Self {
+			prefixes: Arc::new(config.metrics_prefix),
+
+			sessions: gauges("sessions", "Active sessions", &["kind"]),
+			announced: gauges("announced", "Announced paths", &["source"]),
+			subscriptions: gauge("subscriptions", "Active subscriptions being served"),
+			router_queue: gauge("router_queue", "Subscribe requests waiting to be routed"),
+
+			sessions_total: counters("sessions_total", "Sessions established", &["kind"]),
+			subscribes_total: counters("subscribes_total", "Routed subscribe requests", &["result"]),
+			remote_connected: gauges("remote_connected", "Whether a cluster node is connected", &["node"]),
+			remote_errors: counters("remote_errors_total", "Cluster node connection failures", &["node"]),
+
+			groups: counters("groups_total", "Groups relayed", &["direction", "prefix"]),
+			frames: counters("frames_total", "Frames relayed", &["direction", "prefix"]),
+			bytes: counters("bytes_total", "Payload bytes relayed", &["direction", "prefix"]),
+			drops: counters("group_drops_total", "Groups dropped", &["direction", "code"]),
+
+			registry,
+		}
+	}
+
+	/// Encode every metric in the Prometheus text format.
+	pub fn encode(&self, cluster: &Cluster) -> String {
+		let sessions = cluster.sessions.list();
+		let remotes = sessions.iter().filter(|info| info.node.is_some()).count();
+
+		self.sessions
+			.with_label_values(&["client"])
+			.set((sessions.len() - remotes) as i64);
+		self.sessions.with_label_values(&["remote"]).set(remotes as i64);
+
+		let subscriptions: usize = sessions.iter().map(|info| info.session.subscriptions().len()).sum();
+		self.subscriptions.set(subscriptions as i64);
+
+		self.announced
+			.with_label_values(&["local"])
+			.set(cluster.locals.list().len() as i64);
+		self.announced
+			.with_label_values(&["remote"])
+			.set(cluster.remotes.list().len() as i64);
+
+		// Each client session has its own router, while remote sessions share the cluster router.
+		let queued: usize = sessions
+			.iter()
+			.filter_map(|info| info.router.as_ref())
+			.map(|router| router.queued())
+			.sum();
+		self.router_queue.set((cluster.router.queued() + queued) as i64);
+
+		TextEncoder::new()
+			.encode_to_string(&self.registry.gather())
+			.unwrap_or_default()
+	}
+
+	// The label used for a path, bounded by the configured prefixes.
+	fn prefix(&self, path: &str) -> &str {
+		self.prefixes
+			.iter()
+			.filter(|prefix| path.starts_with(prefix.as_str()))
+			.max_by_key(|prefix| prefix.len())
+			.map_or("other", |prefix| prefix.as_str())
+	}
+}
+
+impl moq_transfork::Observer for Metrics {
+	fn group_sent(&self, path: &str) {
+		self.groups.with_label_values(&["out", self.prefix(path)]).inc();
+	}
+
+	fn frame_sent(&self, path: &str, size: usize) {
+		let labels = ["out", self.prefix(path)];
+		self.frames.with_label_values(&labels).inc();
+		self.bytes.with_label_values(&labels).inc_by(size as u64);
+	}
+
+	fn group_received(&self, path: &str) {
+		self.groups.with_label_values(&["in", self.prefix(path)]).inc();
+	}
+
+	fn frame_received(&self, path: &str, size: usize) {
+		let labels = ["in", self.prefix(path)];
+		self.frames.with_label_values(&labels).inc();
+		self.bytes.with_label_values(&labels).inc_by(size as u64);
+	}
+
+	fn drop_sent(&self, _path: &str, code: u32) {
+		self.drops.with_label_values(&["out", &code.to_string()]).inc();
+	}
+
+	fn drop_received(&self, _path: &str, code: u32) {
+		self.drops.with_label_values(&["in", &code.to_string()]).inc();
+	}
+}
@@ -1,39 +1,87 @@
 use std::{
-	collections::{hash_map, HashMap},
+	collections::{hash_map, HashMap, HashSet},
 	sync::{Arc, Mutex},
 };
 
-use moq_transfork::{Announced, AnnouncedConsumer, AnnouncedProducer, Filter, Session};
+use moq_transfork::{Announced, AnnouncedConsumer, AnnouncedProducer, Error, Filter, Session};
+
+use crate::Permissions;
+
+/// What to do when a path is announced by more than one origin.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Conflict {
+	/// The first origin is used, the others are standbys in the order they announced.
+	#[default]
+	FirstWins,
+
+	/// The newest origin is used, the others are standbys in reverse order.
+	LastWins,
+
+	/// Duplicates are ignored, so there are no standbys.
+	Reject,
+}
 
 #[derive(Clone)]
 pub struct Origins {
 	// Tracks announced by clients.
 	unique: AnnouncedProducer,
 
-	// Active routes based on path.
+	// Active routes based on path, with the active origin first and any standbys after.
 	routes: Arc<Mutex<HashMap<String, Vec<Option<Session>>>>>,
+
+	conflict: Conflict,
 }
 
 impl Default for Origins {
 	fn default() -> Self {
-		Self::new()
+		Self::new(Conflict::default())
 	}
 }
 
 impl Origins {
-	pub fn new() -> Self {
+	pub fn new(conflict: Conflict) -> Self {
 		Self {
 			unique: AnnouncedProducer::new(),
 			routes: Default::default(),
+			conflict,
 		}
 	}
 
-	// Route any announcements from the cluster.
-	pub async fn announce(&mut self, mut announced: AnnouncedConsumer, origin: Option<Session>) {
+	// Route any announcements from the cluster, ignoring any paths the origin isn't allowed to publish.
+	// If the origin announces more than `limit` paths, its session is closed.
+	pub async fn announce(
+		&mut self,
+		mut announced: AnnouncedConsumer,
+		origin: Option<Session>,
+		permissions: Permissions,
+		limit: Option<usize>,
+	) {
+		// The paths we've routed, so we only unannounce those.
+		let mut active = HashSet::new();
+
 		while let Some(announced) = announced.next().await {
 			match announced {
-				Announced::Active(am) => self.announce_track(am.to_full(), origin.clone()),
-				Announced::Ended(am) => self.unannounce_track(am.full(), &origin),
+				Announced::Active(am) if !permissions.can_publish(am.full()) => {
+					tracing::warn!(path = am.full(), "unauthorized announce");
+				}
+				Announced::Active(am) if limit.is_some_and(|limit| active.len() >= limit) => {
+					tracing::warn!(path = am.full(), ?limit, "too many announces");
+
+					// Keep going so any routed paths are cleaned up when the session closes.
+					if let Some(session) = origin.clone() {
+						session.close(Error::LimitExceeded);
+					}
+				}
+				Announced::Active(am) => {
+					active.insert(am.full().to_string());
+					self.announce_track(am.to_full(), origin.clone());
+				}
+				Announced::Ended(am) => {
+					// Ignore if we never routed it.
+					if active.remove(am.full()) {
+						self.unannounce_track(am.full(), &origin);
+					}
+				}
 				Announced::Live => {
 					// Ignore.
 				}
@@ -42,34 +90,67 @@ impl Origins {
 	}
 
 	fn announce_track(&mut self, path: String, origin: Option<Session>) {
-		tracing::info!(?path, "announced origin");
-
 		let mut routes = self.routes.lock().unwrap();
-		match routes.entry(path.clone()) {
-			hash_map::Entry::Occupied(mut entry) => entry.get_mut().push(origin),
+
+		let entry = match routes.entry(path.clone()) {
+			hash_map::Entry::Occupied(entry) => entry.into_mut(),
 			hash_map::Entry::Vacant(entry) => {
+				tracing::info!(?path, "announced origin");
+
 				entry.insert(vec![origin]);
 				self.unique.announce(path);
+				return;
+			}
+		};
+
+		// The origin that lost, which we tell so it knows it's not being used.
+		let loser = match self.conflict {
+			Conflict::FirstWins => {
+				tracing::info!(?path, "announced standby origin");
+				entry.push(origin.clone());
+				origin
+			}
+			Conflict::LastWins => {
+				tracing::info!(?path, "announced origin, replacing the active origin");
+				entry.insert(0, origin);
+				entry[1].clone()
+			}
+			Conflict::Reject => {
+				tracing::warn!(?path, "rejected duplicate origin");
+				origin
 			}
+		};
+
+		// Only the announcement is rejected, the rest of the session is unaffected.
+		if let Some(session) = loser {
+			session.reject_announce(&path, Error::Duplicate);
 		}
 	}
 
 	fn unannounce_track(&mut self, path: &str, origin: &Option<Session>) {
-		tracing::info!(?path, "unannounced origin");
-
 		let mut routes = self.routes.lock().unwrap();
 		let entry = match routes.entry(path.to_string()) {
 			hash_map::Entry::Occupied(entry) => entry.into_mut(),
 			hash_map::Entry::Vacant(_) => return,
 		};
 
-		// Technically this is wrong, as it will remove more than one None value.
-		// But currently there can only be one None that will never be removed, so it's fine.
-		entry.retain(|s| s != origin);
+		// A rejected origin was never added, so there's nothing to remove.
+		let index = match entry.iter().position(|s| s == origin) {
+			Some(index) => index,
+			None => return,
+		};
+
+		entry.remove(index);
 
 		if entry.is_empty() {
+			tracing::info!(?path, "unannounced origin");
+
 			routes.remove(path);
 			self.unique.unannounce(path);
+		} else if index == 0 {
+			tracing::info!(?path, standby = entry.len(), "unannounced origin, failing over");
+		} else {
+			tracing::info!(?path, "unannounced standby origin");
 		}
 	}
 
@@ -77,11 +158,79 @@ impl Origins {
 		self.unique.subscribe(filter)
 	}
 
+	/// Returns every announced path along with the sessions that announced it, active origin first.
+	/// A `None` origin means the path was announced by this relay itself.
+	pub fn list(&self) -> Vec<(String, Vec<Option<Session>>)> {
+		let routes = self.routes.lock().unwrap();
+
+		let mut list: Vec<_> = routes
+			.iter()
+			.map(|(path, origins)| (path.clone(), origins.clone()))
+			.collect();
+		list.sort_by(|a, b| a.0.cmp(&b.0));
+		list
+	}
+
+	/// Forcibly remove a path regardless of who announced it, returning false if it wasn't announced.
+	///
+	/// The origins are not notified and won't re-announce a path they still consider active.
+	/// The path stays unannounced until an origin announces it again, ex. after it reconnects.
+	pub fn unannounce(&mut self, path: &str) -> bool {
+		let mut routes = self.routes.lock().unwrap();
+		if routes.remove(path).is_none() {
+			return false;
+		}
+
+		tracing::warn!(?path, "forcibly unannounced");
+		self.unique.unannounce(path);
+
+		true
+	}
+
 	pub fn route(&self, path: &str) -> Option<Session> {
-		// Return the session that most recently announced the path.
+		// Return the active origin, skipping this relay which can't be subscribed to.
 		let routes = self.routes.lock().unwrap();
 
 		let available = routes.get(path)?;
-		available.iter().find(|route| route.is_some()).cloned().unwrap()
+		available.iter().flatten().next().cloned()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures::FutureExt;
+
+	// Returns the next announcement without blocking, as +path or -path.
+	fn next(announced: &mut AnnouncedConsumer) -> Option<String> {
+		match announced.next().now_or_never()?? {
+			Announced::Active(am) => Some(format!("+{}", am.full())),
+			Announced::Ended(am) => Some(format!("-{}", am.full())),
+			Announced::Live => Some("live".to_string()),
+		}
+	}
+
+	#[test]
+	fn unannounce() {
+		let mut origins = Origins::default();
+		let mut announced = origins.announced(Filter::Any);
+
+		origins.announce_track("station1".to_string(), None);
+		assert_eq!(next(&mut announced).as_deref(), Some("+station1"));
+
+		assert!(origins.unannounce("station1"));
+		assert_eq!(next(&mut announced).as_deref(), Some("-station1"));
+		assert!(origins.route("station1").is_none());
+		assert!(origins.list().is_empty());
+		assert!(!origins.unannounce("station1"));
+
+		// The origin's own unannounce is ignored now that the path is gone.
+		origins.unannounce_track("station1", &None);
+		assert_eq!(next(&mut announced), None);
+
+		// The path is available again once an origin announces it.
+		origins.announce_track("station1".to_string(), None);
+		assert_eq!(next(&mut announced).as_deref(), Some("+station1"));
+		assert_eq!(origins.list().len(), 1);
 	}
 }
@@ -0,0 +1,485 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	fs,
+	io::{self, Read, Seek, SeekFrom, Write},
+	path::PathBuf,
+	sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use clap::Parser;
+use moq_transfork::{Filter, GroupConsumer, Retention, Track, TrackConsumer};
+
+// The extension used for each archive file.
+const EXTENSION: &str = "moq";
+
+// Each group is stored as a header (sequence, body length), followed by a body of (frame size, frame) pairs.
+const HEADER_SIZE: u64 = 16;
+
+#[derive(Clone, Parser)]
+pub struct RecordConfig {
+	/// Record every group of any track matching this pattern, ex. `radio/*`. Can be provided multiple times.
+	/// Recorded tracks can still be subscribed to after the publisher has gone.
+	#[arg(long, requires = "record_dir")]
+	pub record: Vec<String>,
+
+	/// The directory used to store recordings, with one append-only file per track.
+	#[arg(long)]
+	pub record_dir: Option<PathBuf>,
+}
+
+/// Records groups to disk and replays them later, ex. to rewind a live station or replay a show.
+#[derive(Clone, Default)]
+pub struct Recorder {
+	dir: Option<PathBuf>,
+	filters: Arc<Vec<Filter>>,
+
+	// Every known archive, keyed by track path.
+	archives: Arc<Mutex<HashMap<String, Arc<Mutex<Archive>>>>>,
+
+	// Recordings loaded into memory, shared by every subscriber, keyed by track path.
+	replays: Arc<Mutex<HashMap<String, Replay>>>,
+}
+
+// A recording loaded into memory.
+struct Replay {
+	// The largest recorded sequence when it was loaded.
+	last: Option<u64>,
+
+	// Never read, so each clone starts from scratch.
+	track: TrackConsumer,
+}
+
+impl Recorder {
+	pub fn new(config: RecordConfig) -> anyhow::Result<Self> {
+		let dir = match config.record_dir {
+			Some(dir) => dir,
+			None => return Ok(Self::default()),
+		};
+
+		fs::create_dir_all(&dir).context("failed to create record directory")?;
+
+		// Index any existing archives so they can be replayed immediately.
+		let mut archives = HashMap::new();
+		for entry in fs::read_dir(&dir).context("failed to read record directory")? {
+			let file = entry?.path();
+			if file.extension().is_none_or(|ext| ext != EXTENSION) {
+				continue;
+			}
+
+			let path = match file.file_stem().and_then(|stem| stem.to_str()).and_then(decode_path) {
+				Some(path) => path,
+				None => {
+					tracing::warn!(?file, "ignoring unknown recording");
+					continue;
+				}
+			};
+
+			let archive = Archive::open(file.clone()).with_context(|| format!("failed to open {:?}", file))?;
+			tracing::info!(%path, groups = archive.index.len(), "found recording");
+
+			archives.insert(path, Arc::new(Mutex::new(archive)));
+		}
+
+		Ok(Self {
+			filters: Arc::new(config.record.iter().map(|pattern| Filter::new(pattern)).collect()),
+			dir: Some(dir),
+			archives: Arc::new(Mutex::new(archives)),
+			replays: Default::default(),
+		})
+	}
+
+	/// Returns true if any tracks should be recorded.
+	pub fn enabled(&self) -> bool {
+		self.dir.is_some() && !self.filters.is_empty()
+	}
+
+	/// Returns true if the path should be recorded.
+	pub fn matches(&self, path: &str) -> bool {
+		self.dir.is_some() && self.filters.iter().any(|filter| filter.matches(path).is_some())
+	}
+
+	/// Record every group of the track until it ends.
+	///
+	/// Sequences continue from the end of any existing recording, so a restarted publisher doesn't overwrite it.
+	#[tracing::instrument("record", skip_all, fields(path = %track.path))]
+	pub async fn record(&self, mut track: TrackConsumer) -> anyhow::Result<()> {
+		let archive = self.archive(&track.path)?;
+
+		// Added to each sequence, in case the publisher restarted from zero.
+		let mut offset = None;
+
+		// NOTE: We get every group the relay retains, even if we fall behind.
+		while let Some(group) = track.next_group().await? {
+			let offset = *offset.get_or_insert_with(|| sequence_offset(archive.lock().unwrap().last(), group.sequence));
+
+			let archive = archive.clone();
+			tokio::spawn(async move {
+				let sequence = group.sequence + offset;
+				if let Err(err) = Self::record_group(archive, group, sequence).await {
+					tracing::warn!(?err, sequence, "failed to record group");
+				}
+			});
+		}
+
+		Ok(())
+	}
+
+	// Wait for the entire group, then append it to the archive.
+	async fn record_group(archive: Arc<Mutex<Archive>>, mut group: GroupConsumer, sequence: u64) -> anyhow::Result<()> {
+		let mut frames = Vec::new();
+		while let Some(frame) = group.read_frame().await? {
+			frames.push(frame);
+		}
+
+		tokio::task::spawn_blocking(move || archive.lock().unwrap().append(sequence, &frames)).await?
+	}
+
+	/// Returns a track containing every recorded group, or None if the path was never recorded.
+	///
+	/// Every group is loaded into memory and retained, so subscribers can request any range via the subscribe start/end.
+	/// The loaded recording is shared by every subscriber, and only reloaded once more groups have been recorded.
+	pub async fn replay(&self, track: Track) -> Option<anyhow::Result<TrackConsumer>> {
+		let archive = self.archives.lock().unwrap().get(&track.path).cloned()?;
+		let replays = self.replays.clone();
+
+		let res = tokio::task::spawn_blocking(move || {
+			// NOTE: Holding the archive lock means concurrent subscribers wait for a single load.
+			let mut archive = archive.lock().unwrap();
+			let last = archive.last();
+
+			if let Some(replay) = replays.lock().unwrap().get(&track.path) {
+				if replay.last == last {
+					return Ok(replay.track.clone());
+				}
+			}
+
+			let groups = archive.read_all()?;
+
+			let path = track.path.clone();
+			let (mut producer, consumer) = track.produce();
+			producer.set_retention(Retention {
+				groups: usize::MAX,
+				..Default::default()
+			});
+
+			for (sequence, frames) in groups {
+				let mut group = producer.create_group(sequence);
+				for frame in frames {
+					group.write_frame(frame);
+				}
+			}
+
+			// NOTE: The consumer keeps the groups after the producer is dropped, and then ends once they're served.
+			let replay = Replay {
+				last,
+				track: consumer.clone(),
+			};
+			replays.lock().unwrap().insert(path, replay);
+
+			Ok(consumer)
+		})
+		.await;
+
+		Some(res.unwrap_or_else(|err| Err(err.into())))
+	}
+
+	// Returns the archive for a path, creating it if needed.
+	fn archive(&self, path: &str) -> anyhow::Result<Arc<Mutex<Archive>>> {
+		let dir = self.dir.as_ref().context("recording disabled")?;
+
+		let mut archives = self.archives.lock().unwrap();
+		if let Some(archive) = archives.get(path) {
+			return Ok(archive.clone());
+		}
+
+		let file = dir.join(encode_path(path)).with_extension(EXTENSION);
+		let archive = Arc::new(Mutex::new(Archive::open(file)?));
+		archives.insert(path.to_string(), archive.clone());
+
+		Ok(archive)
+	}
+}
+
+// An append-only file of groups, with an in-memory index of where each one starts.
+struct Archive {
+	file: fs::File,
+
+	// The offset and body length of each group, by sequence.
+	index: BTreeMap<u64, (u64, u64)>,
+}
+
+impl Archive {
+	fn open(path: PathBuf) -> io::Result<Self> {
+		let mut file = fs::OpenOptions::new()
+			.read(true)
+			.append(true)
+			.create(true)
+			.open(&path)?;
+
+		let len = file.metadata()?.len();
+		let mut index = BTreeMap::new();
+		let mut offset = 0;
+
+		while offset + HEADER_SIZE <= len {
+			let mut header = [0u8; HEADER_SIZE as usize];
+			file.seek(SeekFrom::Start(offset))?;
+			file.read_exact(&mut header)?;
+
+			let mut header = &header[..];
+			let sequence = header.get_u64();
+			let size = header.get_u64();
+
+			if size > len - offset - HEADER_SIZE {
+				break;
+			}
+
+			index.insert(sequence, (offset + HEADER_SIZE, size));
+			offset += HEADER_SIZE + size;
+		}
+
+		// Discard a partially written group, ex. if we crashed.
+		if offset < len {
+			tracing::warn!(?path, bytes = len - offset, "truncating incomplete recording");
+			file.set_len(offset)?;
+		}
+
+		Ok(Self { file, index })
+	}
+
+	// The largest recorded sequence.
+	fn last(&self) -> Option<u64> {
+		self.index.keys().next_back().copied()
+	}
+
+	fn append(&mut self, sequence: u64, frames: &[Bytes]) -> anyhow::Result<()> {
+		if self.index.contains_key(&sequence) {
+			anyhow::bail!("duplicate group");
+		}
+
+		let size: usize = frames.iter().map(|frame| 4 + frame.len()).sum();
+
+		let mut buf = BytesMut::with_capacity(HEADER_SIZE as usize + size);
+		buf.put_u64(sequence);
+		buf.put_u64(size as u64);
+
+		for frame in frames {
+			buf.put_u32(frame.len().try_into().context("frame too large")?);
+			buf.put_slice(frame);
+		}
+
+		let offset = self.file.metadata()?.len();
+
+		// Remove any partially written group, otherwise it would hide every later group when reopened.
+		if let Err(err) = self.file.write_all(&buf) {
+			self.file.set_len(offset)?;
+			return Err(err.into());
+		}
+
+		self.index.insert(sequence, (offset + HEADER_SIZE, size as u64));
+
+		Ok(())
+	}
+
+	// Read every group, in sequence order.
+	fn read_all(&mut self) -> anyhow::Result<Vec<(u64, Vec<Bytes>)>> {
+		let mut groups = Vec::with_capacity(self.index.len());
+
+		for (&sequence, &(offset, size)) in &self.index {
+			let mut body = vec![0u8; size as usize];
+			self.file.seek(SeekFrom::Start(offset))?;
+			self.file.read_exact(&mut body)?;
+
+			let mut body = Bytes::from(body);
+			let mut frames = Vec::new();
+
+			while body.has_remaining() {
+				anyhow::ensure!(body.remaining() >= 4, "corrupt recording");
+				let size = body.get_u32() as usize;
+
+				anyhow::ensure!(body.remaining() >= size, "corrupt recording");
+				frames.push(body.split_to(size));
+			}
+
+			groups.push((sequence, frames));
+		}
+
+		Ok(groups)
+	}
+}
+
+// The amount added to each recorded sequence, so a publisher that restarted from `first` continues after `last`.
+fn sequence_offset(last: Option<u64>, first: u64) -> u64 {
+	match last {
+		Some(last) if first <= last => last + 1 - first,
+		_ => 0,
+	}
+}
+
+// Escape a track path so it's a safe file name, ex. `radio/jazz` becomes `radio%2Fjazz`.
+fn encode_path(path: &str) -> String {
+	let mut encoded = String::with_capacity(path.len());
+
+	for byte in path.bytes() {
+		match byte {
+			b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => encoded.push(byte as char),
+			_ => encoded.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+
+	encoded
+}
+
+fn decode_path(encoded: &str) -> Option<String> {
+	let mut bytes = Vec::with_capacity(encoded.len());
+	let mut rest = encoded.as_bytes();
+
+	while let Some((&byte, remain)) = rest.split_first() {
+		rest = match byte {
+			b'%' => {
+				let hex = std::str::from_utf8(remain.get(..2)?).ok()?;
+				bytes.push(u8::from_str_radix(hex, 16).ok()?);
+				&remain[2..]
+			}
+			_ => {
+				bytes.push(byte);
+				remain
+			}
+		};
+	}
+
+	String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	// Tests run in parallel, so each gets its own file.
+	fn temp_file() -> PathBuf {
+		static NEXT: AtomicUsize = AtomicUsize::new(0);
+		let name = format!(
+			"moq-relay-test-{}-{}.{}",
+			std::process::id(),
+			NEXT.fetch_add(1, Ordering::Relaxed),
+			EXTENSION
+		);
+		std::env::temp_dir().join(name)
+	}
+
+	fn frames(frames: &[&'static [u8]]) -> Vec<Bytes> {
+		frames.iter().map(|frame| Bytes::from_static(frame)).collect()
+	}
+
+	#[test]
+	fn round_trip() {
+		let path = temp_file();
+
+		let mut archive = Archive::open(path.clone()).unwrap();
+		archive.append(0, &frames(&[b"hello", b"world"])).unwrap();
+		archive.append(1, &frames(&[])).unwrap();
+		archive.append(2, &frames(&[b""])).unwrap();
+		drop(archive);
+
+		let mut archive = Archive::open(path.clone()).unwrap();
+		assert_eq!(archive.last(), Some(2));
+		assert_eq!(
+			archive.read_all().unwrap(),
+			vec![
+				(0, frames(&[b"hello", b"world"])),
+				(1, frames(&[])),
+				(2, frames(&[b""])),
+			]
+		);
+
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn truncated() {
+		let path = temp_file();
+
+		let mut archive = Archive::open(path.clone()).unwrap();
+		archive.append(0, &frames(&[b"hello"])).unwrap();
+		drop(archive);
+
+		let len = fs::metadata(&path).unwrap().len();
+
+		// A header claiming more bytes than were written, ex. if we crashed mid-write.
+		let mut partial = BytesMut::new();
+		partial.put_u64(1);
+		partial.put_u64(100);
+		partial.put_slice(b"partial");
+
+		let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+		file.write_all(&partial).unwrap();
+		drop(file);
+
+		let mut archive = Archive::open(path.clone()).unwrap();
+		assert_eq!(fs::metadata(&path).unwrap().len(), len);
+		assert_eq!(archive.read_all().unwrap(), vec![(0, frames(&[b"hello"]))]);
+
+		// New groups are appended after the last complete group.
+		archive.append(1, &frames(&[b"world"])).unwrap();
+		drop(archive);
+
+		let mut archive = Archive::open(path.clone()).unwrap();
+		assert_eq!(
+			archive.read_all().unwrap(),
+			vec![(0, frames(&[b"hello"])), (1, frames(&[b"world"]))]
+		);
+
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn duplicate() {
+		let path = temp_file();
+
+		let mut archive = Archive::open(path.clone()).unwrap();
+		archive.append(5, &frames(&[b"first"])).unwrap();
+		assert!(archive.append(5, &frames(&[b"second"])).is_err());
+		assert_eq!(archive.read_all().unwrap(), vec![(5, frames(&[b"first"]))]);
+
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn offset() {
+		// A new recording, or a publisher that continues where it left off.
+		assert_eq!(sequence_offset(None, 0), 0);
+		assert_eq!(sequence_offset(None, 7), 0);
+		assert_eq!(sequence_offset(Some(4), 5), 0);
+		assert_eq!(sequence_offset(Some(4), 10), 0);
+
+		// A publisher that restarted, so its groups are recorded after the existing ones.
+		assert_eq!(sequence_offset(Some(4), 0), 5);
+		assert_eq!(sequence_offset(Some(4), 4), 1);
+		assert_eq!(sequence_offset(Some(10), 3), 8);
+	}
+
+	#[test]
+	fn path() {
+		assert_eq!(encode_path("radio/jazz"), "radio%2Fjazz");
+		assert_eq!(encode_path("100%"), "100%25");
+
+		for path in [
+			"radio/jazz",
+			"100%",
+			"a%2Fb",
+			"station-1_hd",
+			"../etc/passwd",
+			"ünicode",
+		] {
+			let encoded = encode_path(path);
+			assert!(!encoded.contains('/'));
+			assert_eq!(decode_path(&encoded).as_deref(), Some(path));
+		}
+
+		assert_eq!(decode_path("bad%2"), None);
+		assert_eq!(decode_path("bad%zz"), None);
+	}
+}
@@ -0,0 +1,83 @@
+use std::{
+	collections::HashMap,
+	net,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::SystemTime,
+};
+
+use moq_transfork::{RouterConsumer, Session};
+
+/// An active session, either a client connected to us or a cluster node we connected to.
+#[derive(Clone)]
+pub struct SessionInfo {
+	pub id: u64,
+	pub addr: Option<net::SocketAddr>,
+	pub connected: SystemTime,
+
+	// The hostname of the remote cluster node, if we connected to it.
+	pub node: Option<String>,
+
+	pub session: Session,
+
+	// The router used for the session's subscriptions, if it has its own.
+	pub router: Option<RouterConsumer>,
+}
+
+/// A registry of active sessions, used by the admin API.
+#[derive(Clone, Default)]
+pub struct Sessions {
+	next: Arc<AtomicU64>,
+	active: Arc<Mutex<HashMap<u64, SessionInfo>>>,
+}
+
+impl Sessions {
+	/// Allocate a unique ID for a new session, used in logs even before it's registered.
+	pub fn next_id(&self) -> u64 {
+		self.next.fetch_add(1, Ordering::Relaxed)
+	}
+
+	/// Register an established session, which is removed when the returned guard is dropped.
+	pub fn register(&self, info: SessionInfo) -> SessionGuard {
+		let id = info.id;
+		self.active.lock().unwrap().insert(id, info);
+
+		SessionGuard {
+			id,
+			sessions: self.clone(),
+		}
+	}
+
+	pub fn list(&self) -> Vec<SessionInfo> {
+		let mut list: Vec<_> = self.active.lock().unwrap().values().cloned().collect();
+		list.sort_by_key(|info| info.id);
+		list
+	}
+
+	pub fn get(&self, id: u64) -> Option<SessionInfo> {
+		self.active.lock().unwrap().get(&id).cloned()
+	}
+
+	/// Returns the ID of a registered session.
+	pub fn find(&self, session: &Session) -> Option<u64> {
+		self.active
+			.lock()
+			.unwrap()
+			.values()
+			.find(|info| &info.session == session)
+			.map(|info| info.id)
+	}
+}
+
+pub struct SessionGuard {
+	id: u64,
+	sessions: Sessions,
+}
+
+impl Drop for SessionGuard {
+	fn drop(&mut self) {
+		self.sessions.active.lock().unwrap().remove(&self.id);
+	}
+}
@@ -1,30 +1,30 @@
-use std::{
-	net,
-	pin::Pin,
-	task::{ready, Context, Poll},
-};
+use std::{collections::HashMap, net, ops::Bound, time::Duration};
 
 use axum::{
 	body::Body,
-	extract::Path,
-	http::{Method, StatusCode},
-	response::{IntoResponse, Response},
+	extract::{Path, Query},
+	http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+	response::{
+		sse::{Event, KeepAlive, Sse},
+		IntoResponse, Response,
+	},
 	routing::get,
-	Router,
+	Json, Router,
 };
 use bytes::Bytes;
 use futures::FutureExt;
 use hyper_serve::accept::DefaultAcceptor;
-use moq_transfork::Filter;
-use std::future::Future;
+use moq_transfork::{Announced, Filter};
+use serde::{Deserialize, Serialize};
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::Cluster;
+use crate::{admin_routes, Auth, Cluster, Permissions, RemoteHealth, INTERNAL_PREFIX};
 
 pub struct WebConfig {
 	pub bind: net::SocketAddr,
 	pub tls: moq_native::tls::Config,
 	pub cluster: Cluster,
+	pub auth: Auth,
 }
 
 // Run a HTTP server using Axum
@@ -42,28 +42,56 @@ impl Web {
 
 		let app = Router::new()
 			.route("/fingerprint", get(fingerprint))
+			.route(
+				"/cluster",
+				get({
+					let cluster = config.cluster.clone();
+					move || serve_cluster(cluster.clone())
+				}),
+			)
+			.route(
+				"/metrics",
+				get({
+					let cluster = config.cluster.clone();
+					move || async move { cluster.metrics.encode(&cluster) }
+				}),
+			)
 			.route(
 				"/announced",
 				get({
 					let cluster = config.cluster.clone();
-					move || serve_announced(Path("".to_string()), cluster.clone())
+					let auth = config.auth.clone();
+					move |query, headers| {
+						serve_announced(Path("".to_string()), query, headers, cluster.clone(), auth.clone())
+					}
 				}),
 			)
 			.route(
 				"/announced/{*prefix}",
 				get({
 					let cluster = config.cluster.clone();
-					move |path| serve_announced(path, cluster)
+					let auth = config.auth.clone();
+					move |path, query, headers| serve_announced(path, query, headers, cluster, auth)
 				}),
 			)
 			.route(
 				"/fetch/{*path}",
 				get({
 					let cluster = config.cluster.clone();
-					move |path| serve_fetch(path, cluster)
+					let auth = config.auth.clone();
+					move |path, query| serve_fetch(path, query, cluster, auth)
 				}),
 			)
-			.layer(CorsLayer::new().allow_origin(Any).allow_methods([Method::GET]));
+			.layer(
+				CorsLayer::new()
+					.allow_origin(Any)
+					.allow_methods([Method::GET])
+					.expose_headers([
+						HeaderName::from_static("moq-group"),
+						HeaderName::from_static("moq-frames"),
+					]),
+			)
+			.merge(admin_routes(config.cluster.clone(), config.auth.clone()));
 
 		let server = hyper_serve::bind(config.bind);
 
@@ -76,8 +104,27 @@ impl Web {
 	}
 }
 
-/// Serve the announced tracks for a given prefix.
-async fn serve_announced(Path(path): Path<String>, cluster: Cluster) -> impl IntoResponse {
+#[derive(Deserialize)]
+struct AnnouncedQuery {
+	/// The same token used for WebTransport, required if auth is enabled and the tracks aren't public.
+	jwt: Option<String>,
+}
+
+/// Serve the announced tracks for a given prefix, limited to the tracks the client is allowed to subscribe to.
+///
+/// If the client accepts `text/event-stream` (ex. `EventSource`), then announcements are streamed as they happen.
+async fn serve_announced(
+	Path(path): Path<String>,
+	Query(query): Query<AnnouncedQuery>,
+	headers: HeaderMap,
+	cluster: Cluster,
+	auth: Auth,
+) -> Response {
+	let permissions = match auth.verify_token(query.jwt.as_deref()) {
+		Ok(permissions) => permissions,
+		Err(err) => return (StatusCode::UNAUTHORIZED, err.to_string()).into_response(),
+	};
+
 	// Make anything without a / prefix private.
 	let filter = if path.is_empty() {
 		Filter::Any
@@ -90,6 +137,15 @@ async fn serve_announced(Path(path): Path<String>, cluster: Cluster) -> impl Int
 		Filter::Prefix(path)
 	};
 
+	let stream = headers
+		.get(header::ACCEPT)
+		.and_then(|accept| accept.to_str().ok())
+		.is_some_and(|accept| accept.contains("text/event-stream"));
+
+	if stream {
+		return stream_announced(filter, cluster, permissions).into_response();
+	}
+
 	let mut local = cluster.locals.announced(filter.clone());
 	let mut remote = cluster.remotes.announced(filter);
 
@@ -107,15 +163,120 @@ async fn serve_announced(Path(path): Path<String>, cluster: Cluster) -> impl Int
 		}
 	}
 
-	tracks.join("\n")
+	// Cluster internals are not public.
+	tracks.retain(|track| !track.starts_with(INTERNAL_PREFIX) && permissions.can_subscribe(track));
+
+	tracks.join("\n").into_response()
+}
+
+/// Stream announcements as server-sent events until the client disconnects.
+///
+/// Each `active` and `ended` event contains `{"path": ...}`, followed by a single `live` event once caught up.
+fn stream_announced(filter: Filter, cluster: Cluster, permissions: Permissions) -> impl IntoResponse {
+	// Cluster internals are not public.
+	let announced = cluster.announced(
+		filter,
+		move |path| !path.starts_with(INTERNAL_PREFIX) && permissions.can_subscribe(path),
+		std::future::pending(),
+	);
+
+	let events = futures::stream::unfold(announced, |mut announced| async move {
+		let event = match announced.next().await? {
+			Announced::Active(am) => Event::default()
+				.event("active")
+				.json_data(AnnouncedEvent { path: am.full() }),
+			Announced::Ended(am) => Event::default()
+				.event("ended")
+				.json_data(AnnouncedEvent { path: am.full() }),
+			// NOTE: Browsers ignore events without data.
+			Announced::Live => Ok(Event::default().event("live").data("{}")),
+		};
+
+		Some((event, announced))
+	});
+
+	Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[derive(Serialize)]
+struct AnnouncedEvent<'a> {
+	path: &'a str,
+}
+
+#[derive(Serialize)]
+struct ClusterHealth {
+	roots: HashMap<String, RemoteHealth>,
+	remotes: HashMap<String, RemoteHealth>,
+}
+
+/// Serve the health of our connections to other cluster nodes.
+async fn serve_cluster(cluster: Cluster) -> Json<ClusterHealth> {
+	Json(ClusterHealth {
+		roots: cluster.roots.list(),
+		remotes: cluster.health.list(),
+	})
+}
+
+#[derive(Deserialize)]
+struct FetchQuery {
+	/// The same token used for WebTransport, required if auth is enabled and the track isn't public.
+	jwt: Option<String>,
+
+	/// Fetch a specific group, waiting for it if it hasn't been produced yet.
+	group: Option<u64>,
+
+	/// Fetch the first group after this sequence, waiting for it if needed (long-poll).
+	after: Option<u64>,
+
+	/// Fetch any cached groups within this inclusive range.
+	start: Option<u64>,
+	end: Option<u64>,
+
+	/// Fetch up to this many of the latest cached groups.
+	latest: Option<usize>,
+
+	/// How long to wait for a group, in milliseconds.
+	timeout: Option<u64>,
+
+	/// Either "asc" or "desc" (default), used when subscribing upstream.
+	order: Option<String>,
 }
 
-/// Serve the latest group for a given track
-async fn serve_fetch(Path(path): Path<String>, cluster: Cluster) -> axum::response::Result<ServeGroup> {
+// How long to wait for a group unless the request says otherwise.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The boundary used for multipart responses.
+const BOUNDARY: &str = "moq-group";
+
+/// Serve one or more groups for a given track.
+///
+/// A single group is returned as the concatenated frames, with the `moq-group` and `moq-frames` (frame sizes) headers.
+/// Multiple groups are returned as `multipart/mixed`, where each part has the same headers.
+async fn serve_fetch(
+	Path(path): Path<String>,
+	Query(query): Query<FetchQuery>,
+	cluster: Cluster,
+	auth: Auth,
+) -> axum::response::Result<Response> {
+	let permissions = match auth.verify_token(query.jwt.as_deref()) {
+		Ok(permissions) => permissions,
+		Err(err) => return Err((StatusCode::UNAUTHORIZED, err.to_string()).into()),
+	};
+
+	if !permissions.can_subscribe(&path) {
+		return Err(StatusCode::FORBIDDEN.into());
+	}
+
+	let order = match query.order.as_deref() {
+		None | Some("desc") => moq_transfork::GroupOrder::Desc,
+		Some("asc") => moq_transfork::GroupOrder::Asc,
+		Some(_) => return Err((StatusCode::BAD_REQUEST, "invalid order").into()),
+	};
+
 	let track = moq_transfork::Track {
 		path,
 		priority: 0,
-		order: moq_transfork::GroupOrder::Desc,
+		order,
 	};
 
 	tracing::info!(?track, "subscribing to track");
@@ -126,82 +287,133 @@ async fn serve_fetch(Path(path): Path<String>, cluster: Cluster) -> axum::respon
 		Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into()),
 	};
 
-	let group = match track.next_group().await {
-		Ok(group) => group,
-		Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into()),
+	let timeout = query.timeout.map_or(FETCH_TIMEOUT, Duration::from_millis);
+
+	// Return multiple groups from the cache.
+	if query.start.is_some() || query.end.is_some() || query.latest.is_some() {
+		let start = query.start.map_or(Bound::Unbounded, Bound::Included);
+		let end = query.end.map_or(Bound::Unbounded, Bound::Included);
+
+		let mut groups = track.cached((start, end));
+		if let Some(latest) = query.latest {
+			groups = groups.split_off(groups.len().saturating_sub(latest));
+		}
+
+		let mut parts = Vec::with_capacity(groups.len());
+		for group in groups {
+			parts.push(read_group(group).await?);
+		}
+
+		return Ok(serve_groups(parts));
+	}
+
+	// Otherwise wait for the first group at or after this sequence, or the latest group if not provided.
+	let first = match (query.group, query.after) {
+		(Some(_), Some(_)) => return Err((StatusCode::BAD_REQUEST, "group and after are exclusive").into()),
+		(Some(group), None) => Some(group),
+		(None, Some(after)) => Some(after + 1),
+		(None, None) => None,
 	};
 
-	let group = match group {
-		Some(group) => group,
-		None => return Err(StatusCode::NO_CONTENT.into()),
+	let group = match tokio::time::timeout(timeout, next_group(&mut track, first)).await {
+		Ok(Ok(Some(group))) => group,
+		Ok(Ok(None)) => return Err(StatusCode::NO_CONTENT.into()),
+		Ok(Err(e)) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into()),
+		// Long-poll timed out, so the client should try again.
+		Err(_) => return Err(StatusCode::NO_CONTENT.into()),
 	};
 
-	Ok(ServeGroup::new(group))
-}
+	if query.group.is_some_and(|sequence| sequence != group.sequence) {
+		// The requested group was skipped or has been evicted.
+		return Err(StatusCode::NOT_FOUND.into());
+	}
 
-struct ServeGroup {
-	group: moq_transfork::GroupConsumer,
-	frame: Option<moq_transfork::FrameConsumer>,
+	let (sequence, frames) = read_group(group).await?;
+
+	let mut response = Response::new(Body::from(frames.concat()));
+	set_group_headers(response.headers_mut(), sequence, &frames);
+
+	Ok(response)
 }
 
-impl ServeGroup {
-	fn new(group: moq_transfork::GroupConsumer) -> Self {
-		Self { group, frame: None }
+// Return the first group with a sequence of at least `first`, checking the cache before waiting.
+async fn next_group(
+	track: &mut moq_transfork::TrackConsumer,
+	first: Option<u64>,
+) -> moq_transfork::Result<Option<moq_transfork::GroupConsumer>> {
+	if let Some(first) = first {
+		if let Some(group) = track.cached(first..).into_iter().next() {
+			return Ok(Some(group));
+		}
 	}
 
-	async fn next(&mut self) -> moq_transfork::Result<Option<Bytes>> {
-		loop {
-			if let Some(frame) = self.frame.as_mut() {
-				let data = frame.read_all().await?;
-				self.frame.take();
-				return Ok(Some(data));
-			}
-
-			self.frame = self.group.next_frame().await?;
-			if self.frame.is_none() {
-				return Ok(None);
-			}
+	while let Some(group) = track.next_group().await? {
+		if first.is_none_or(|first| group.sequence >= first) {
+			return Ok(Some(group));
 		}
 	}
-}
 
-impl IntoResponse for ServeGroup {
-	fn into_response(self) -> Response {
-		Response::new(Body::new(self))
-	}
+	Ok(None)
 }
 
-impl http_body::Body for ServeGroup {
-	type Data = Bytes;
-	type Error = ServeGroupError;
-
-	fn poll_frame(
-		self: Pin<&mut Self>,
-		cx: &mut Context<'_>,
-	) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
-		let this = self.get_mut();
-
-		// Use `poll_fn` to turn the async function into a Future
-		let future = this.next();
-		tokio::pin!(future);
-
-		match ready!(future.poll(cx)) {
-			Ok(Some(data)) => {
-				let frame = http_body::Frame::data(data);
-				Poll::Ready(Some(Ok(frame)))
-			}
-			Ok(None) => Poll::Ready(None),
-			Err(e) => Poll::Ready(Some(Err(ServeGroupError(e)))),
+// Read an entire group so we can return the frame boundaries.
+async fn read_group(mut group: moq_transfork::GroupConsumer) -> axum::response::Result<(u64, Vec<Bytes>)> {
+	let mut frames = Vec::new();
+
+	loop {
+		match group.read_frame().await {
+			Ok(Some(frame)) => frames.push(frame),
+			Ok(None) => return Ok((group.sequence, frames)),
+			Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into()),
 		}
 	}
 }
 
-#[derive(Debug, thiserror::Error)]
-#[error(transparent)]
-struct ServeGroupError(moq_transfork::Error);
+fn set_group_headers(headers: &mut HeaderMap, sequence: u64, frames: &[Bytes]) {
+	let sizes: Vec<_> = frames.iter().map(|frame| frame.len().to_string()).collect();
+
+	headers.insert(
+		header::CONTENT_TYPE,
+		HeaderValue::from_static("application/octet-stream"),
+	);
+	headers.insert("moq-group", HeaderValue::from(sequence));
+	headers.insert("moq-frames", HeaderValue::from_str(&sizes.join(",")).unwrap());
+}
+
+// Encode multiple groups as a multipart body, oldest first.
+fn serve_groups(groups: Vec<(u64, Vec<Bytes>)>) -> Response {
+	let mut body = Vec::new();
 
-impl IntoResponse for ServeGroupError {
-	fn into_response(self) -> Response {
-		(StatusCode::INTERNAL_SERVER_ERROR, self.0.to_string()).into_response()
+	for (sequence, frames) in groups {
+		let mut headers = HeaderMap::new();
+		set_group_headers(&mut headers, sequence, &frames);
+
+		// The payload is binary and could contain the boundary, so include the length too.
+		let size: usize = frames.iter().map(|frame| frame.len()).sum();
+		headers.insert(header::CONTENT_LENGTH, HeaderValue::from(size));
+
+		body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+		for (name, value) in &headers {
+			body.extend_from_slice(name.as_str().as_bytes());
+			body.extend_from_slice(b": ");
+			body.extend_from_slice(value.as_bytes());
+			body.extend_from_slice(b"\r\n");
+		}
+		body.extend_from_slice(b"\r\n");
+
+		for frame in frames {
+			body.extend_from_slice(&frame);
+		}
+		body.extend_from_slice(b"\r\n");
 	}
+
+	body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+
+	let mut response = Response::new(Body::from(body));
+	response.headers_mut().insert(
+		header::CONTENT_TYPE,
+		HeaderValue::from_str(&format!("multipart/mixed; boundary={}", BOUNDARY)).unwrap(),
+	);
+
+	response
 }
@@ -46,6 +46,18 @@ pub enum Error {
 
 	#[error("protocol violation")]
 	ProtocolViolation,
+
+	/// The session is not allowed to publish or subscribe to the path.
+	#[error("unauthorized")]
+	Unauthorized,
+
+	/// The session exceeded a configured limit, ex. too many subscriptions.
+	#[error("limit exceeded")]
+	LimitExceeded,
+
+	/// The group is older than the track's TTL, so it's no longer worth delivering.
+	#[error("expired")]
+	Expired,
 }
 
 impl Error {
@@ -63,6 +75,9 @@ impl Error {
 			Self::NotFound => 13,
 			Self::WrongSize => 14,
 			Self::ProtocolViolation => 15,
+			Self::Unauthorized => 16,
+			Self::LimitExceeded => 17,
+			Self::Expired => 18,
 			Self::App(app) => *app + 64,
 		}
 	}
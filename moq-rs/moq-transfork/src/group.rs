@@ -8,8 +8,8 @@
 //!
 //! The stream is closed with [ServeError::MoqError] when all writers or readers are dropped.
 use bytes::Bytes;
-use std::ops;
-use tokio::sync::watch;
+use std::{ops, time::Duration};
+use tokio::{sync::watch, time::Instant};
 
 use crate::Error;
 
@@ -30,9 +30,10 @@ impl Group {
 
 	pub fn produce(self) -> (GroupProducer, GroupConsumer) {
 		let (send, recv) = watch::channel(GroupState::default());
+		let created = Instant::now();
 
-		let writer = GroupProducer::new(send, self.clone());
-		let reader = GroupConsumer::new(recv, self);
+		let writer = GroupProducer::new(send, self.clone(), created);
+		let reader = GroupConsumer::new(recv, self, created);
 
 		(writer, reader)
 	}
@@ -64,11 +65,14 @@ pub struct GroupProducer {
 
 	// Immutable stream state.
 	pub info: Group,
+
+	// When the group was created, used to expire old groups.
+	created: Instant,
 }
 
 impl GroupProducer {
-	fn new(state: watch::Sender<GroupState>, info: Group) -> Self {
-		Self { state, info }
+	fn new(state: watch::Sender<GroupState>, info: Group, created: Instant) -> Self {
+		Self { state, info, created }
 	}
 
 	// Write a frame in one go
@@ -90,7 +94,7 @@ impl GroupProducer {
 
 	/// Create a new consumer for the group.
 	pub fn subscribe(&self) -> GroupConsumer {
-		GroupConsumer::new(self.state.subscribe(), self.info.clone())
+		GroupConsumer::new(self.state.subscribe(), self.info.clone(), self.created)
 	}
 
 	/// Close the stream with an error.
@@ -118,6 +122,9 @@ pub struct GroupConsumer {
 	// Immutable stream state.
 	pub info: Group,
 
+	// When the group was created, used to expire old groups.
+	created: Instant,
+
 	// The number of frames we've read.
 	// NOTE: Cloned readers inherit this offset, but then run in parallel.
 	index: usize,
@@ -127,10 +134,11 @@ pub struct GroupConsumer {
 }
 
 impl GroupConsumer {
-	fn new(state: watch::Receiver<GroupState>, group: Group) -> Self {
+	fn new(state: watch::Receiver<GroupState>, group: Group, created: Instant) -> Self {
 		Self {
 			state,
 			info: group,
+			created,
 			index: 0,
 			active: None,
 		}
@@ -179,6 +187,16 @@ impl GroupConsumer {
 		}
 	}
 
+	/// How long ago the group was created (or received, if it came from the network).
+	pub fn age(&self) -> Duration {
+		self.created.elapsed()
+	}
+
+	/// The total size of the frames written thus far, in bytes.
+	pub fn size(&self) -> usize {
+		self.state.borrow().frames.iter().map(|frame| frame.size).sum()
+	}
+
 	pub async fn closed(&self) -> Result<(), Error> {
 		match self.state.clone().wait_for(|state| state.closed.is_err()).await {
 			Ok(state) => state.closed.clone(),
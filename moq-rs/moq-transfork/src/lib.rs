@@ -36,10 +36,12 @@ mod announced;
 mod error;
 mod frame;
 mod group;
+mod observer;
 mod publisher;
 mod reader;
 mod router;
 mod session;
+mod stats;
 mod stream;
 mod subscriber;
 mod track;
@@ -48,11 +50,15 @@ mod writer;
 pub use announced::*;
 pub use frame::*;
 pub use group::*;
+pub use observer::*;
 pub use router::*;
 pub use track::*;
 
+pub use publisher::Subscription;
 pub(crate) use publisher::*;
 pub(crate) use reader::*;
+pub(crate) use stats::Counters;
+pub use stats::{ConnectionStats, SessionStats};
 pub(crate) use stream::*;
 pub(crate) use subscriber::*;
 pub(crate) use writer::*;
@@ -0,0 +1,25 @@
+/// Callbacks for the data flowing through a [crate::Session], ex. to export metrics.
+///
+/// These are called inline so they should be cheap; every method does nothing by default.
+pub trait Observer: Send + Sync {
+	/// A group was sent to the remote in full.
+	fn group_sent(&self, _path: &str) {}
+
+	/// A frame was sent to the remote.
+	fn frame_sent(&self, _path: &str, _size: usize) {}
+
+	/// A group was received from the remote in full.
+	fn group_received(&self, _path: &str) {}
+
+	/// A frame was received from the remote.
+	fn frame_received(&self, _path: &str, _size: usize) {}
+
+	/// We failed to send a group and told the remote with the given error code.
+	fn drop_sent(&self, _path: &str, _code: u32) {}
+
+	/// The remote failed to send a group, with the given error code.
+	fn drop_received(&self, _path: &str, _code: u32) {}
+
+	/// The remote won't use a track we announced, with the given error code, ex. because another publisher has it.
+	fn announce_rejected(&self, _path: &str, _code: u32) {}
+}
@@ -1,25 +1,65 @@
-use std::collections::{hash_map, HashMap};
+use std::{
+	collections::{hash_map, HashMap},
+	ops::Bound,
+	sync::Arc,
+	time::Duration,
+};
 
 use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::watch;
 
 use crate::{
-	Announced, AnnouncedConsumer, AnnouncedProducer, Error, GroupConsumer, GroupOrder, RouterConsumer, Stream, Track,
-	TrackConsumer, Writer,
+	Announced, AnnouncedConsumer, AnnouncedProducer, Counters, Delivery, Error, Filter, GroupConsumer, GroupOrder,
+	Observer, RouterConsumer, Stream, Track, TrackConsumer, TrackEvent, Writer,
 };
 
 use moq_async::{spawn, FuturesExt, Lock, OrClose};
-use moq_proto::message;
+use moq_proto::{coding::Encode, message};
+
+/// A subscription being served to the remote, along with how much has been sent so far.
+#[derive(Clone, Debug)]
+pub struct Subscription {
+	pub id: u64,
+	pub path: String,
+	pub priority: i8,
+	pub order: GroupOrder,
+
+	/// The number of groups that were sent in full.
+	pub groups: u64,
+
+	/// The number of payload bytes sent, across all groups.
+	pub bytes: u64,
+}
+
+// The subscriber's latest preferences, shared with in-flight groups.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Settings {
+	priority: i8,
+	order: GroupOrder,
+
+	// Groups before this are no longer wanted.
+	start: Option<u64>,
+}
 
 #[derive(Clone)]
 pub(super) struct Publisher {
 	session: web_transport::Session,
 	announced: AnnouncedProducer,
 	tracks: Lock<HashMap<String, TrackConsumer>>,
-	router: Lock<Option<RouterConsumer>>,
+
+	// Routers for unknown paths, tried in order.
+	routes: Lock<Vec<(Filter, RouterConsumer)>>,
+
+	subscriptions: Lock<HashMap<u64, Lock<Subscription>>>,
+	observer: Lock<Option<Arc<dyn Observer>>>,
+	counters: Arc<Counters>,
+
+	// The maximum number of concurrent subscriptions we'll serve.
+	max_subscriptions: Lock<Option<usize>>,
 }
 
 impl Publisher {
-	pub fn new(session: web_transport::Session) -> Self {
+	pub fn new(session: web_transport::Session, counters: Arc<Counters>) -> Self {
 		// We start the publisher in live mode because we're producing content.
 		let mut announced = AnnouncedProducer::new();
 		announced.live();
@@ -28,10 +68,31 @@ impl Publisher {
 			session,
 			announced,
 			tracks: Default::default(),
-			router: Default::default(),
+			routes: Default::default(),
+			subscriptions: Default::default(),
+			observer: Default::default(),
+			counters,
+			max_subscriptions: Default::default(),
 		}
 	}
 
+	pub fn set_max_subscriptions(&mut self, max: usize) {
+		self.max_subscriptions.lock().replace(max);
+	}
+
+	pub fn set_observer(&mut self, observer: Arc<dyn Observer>) {
+		self.observer.lock().replace(observer);
+	}
+
+	/// Returns the subscriptions currently being served.
+	pub fn subscriptions(&self) -> Vec<Subscription> {
+		self.subscriptions
+			.lock()
+			.values()
+			.map(|subscription| subscription.lock().clone())
+			.collect()
+	}
+
 	/// Publish a track.
 	#[tracing::instrument("publish", skip_all, err, fields(?track))]
 	pub fn publish(&mut self, track: TrackConsumer) -> Result<(), Error> {
@@ -79,12 +140,13 @@ impl Publisher {
 		});
 	}
 
-	/// Optionally support requests for arbitrary paths using the provided router.
+	/// Optionally support requests for arbitrary paths matching the filter, using the provided router.
 	/// This is an advanced API for producing tracks dynamically.
 	/// NOTE: You may want to call [Self::announce] to advertise these paths.
-	pub fn route(&mut self, router: RouterConsumer) {
-		// TODO support multiple routers?
-		self.router.lock().replace(router);
+	///
+	/// Routers are tried in the order they were added, falling through to the next if one returns [Error::NotFound].
+	pub fn route(&mut self, filter: Filter, router: RouterConsumer) {
+		self.routes.lock().push((filter, router));
 	}
 
 	pub async fn recv_announce(&mut self, stream: &mut Stream) -> Result<(), Error> {
@@ -92,36 +154,93 @@ impl Publisher {
 		let filter = interest.filter;
 		tracing::debug!(?filter, "announce interest");
 
-		let mut announced = self.announced.subscribe(filter);
+		let mut announced = self.announced.subscribe(filter.clone());
 
-		// Flush any synchronously announced paths
-		while let Some(announced) = announced.next().await {
-			match announced {
-				Announced::Active(m) => {
-					let msg = message::Announce::Active(m.capture().to_string());
-					stream.writer.encode(&msg).await?;
-				}
-				Announced::Ended(m) => {
-					let msg = message::Announce::Ended(m.capture().to_string());
-					stream.writer.encode(&msg).await?;
-				}
-				Announced::Live => {
-					// Indicate that we're caught up to live.
-					stream.writer.encode(&message::Announce::Live).await?;
-				}
+		// Set once the subscriber has finished sending errors.
+		let mut complete = false;
+
+		loop {
+			tokio::select! {
+				// Flush any synchronously announced paths
+				res = announced.next() => match res {
+					Some(Announced::Active(m)) => {
+						let msg = message::Announce::Active(m.capture().to_string());
+						stream.writer.encode(&msg).await?;
+					}
+					Some(Announced::Ended(m)) => {
+						let msg = message::Announce::Ended(m.capture().to_string());
+						stream.writer.encode(&msg).await?;
+					}
+					Some(Announced::Live) => {
+						// Indicate that we're caught up to live.
+						stream.writer.encode(&message::Announce::Live).await?;
+					}
+					None => return Ok(()),
+				},
+				res = stream.reader.decode_maybe::<message::AnnounceError>(), if !complete => match res? {
+					Some(err) => {
+						let path = filter.reconstruct(&err.suffix);
+						tracing::warn!(?path, code = err.code, "announce rejected");
+
+						if let Some(observer) = self.observer.lock().as_ref() {
+							observer.announce_rejected(&path, err.code);
+						}
+					}
+					None => complete = true,
+				},
 			}
 		}
-
-		Ok(())
 	}
 
 	pub async fn recv_subscribe(&mut self, stream: &mut Stream) -> Result<(), Error> {
-		let subscribe = stream.reader.decode().await?;
-		self.serve_subscribe(stream, subscribe).await
+		let subscribe: message::Subscribe = stream.reader.decode().await?;
+		let id = subscribe.id;
+
+		let max = *self.max_subscriptions.lock();
+		let stats = Self::reserve(&self.subscriptions, max, &subscribe)?;
+
+		let res = self.serve_subscribe(stream, subscribe, stats).await;
+		self.subscriptions.lock().remove(&id);
+
+		res
+	}
+
+	// Take a slot for the subscription before any awaits, so concurrent subscribes can't exceed the limit.
+	fn reserve(
+		subscriptions: &Lock<HashMap<u64, Lock<Subscription>>>,
+		max: Option<usize>,
+		subscribe: &message::Subscribe,
+	) -> Result<Lock<Subscription>, Error> {
+		let mut subscriptions = subscriptions.lock();
+
+		if max.is_some_and(|max| subscriptions.len() >= max) {
+			return Err(Error::LimitExceeded);
+		}
+
+		let stats = Lock::new(Subscription {
+			id: subscribe.id,
+			path: subscribe.path.clone(),
+			priority: subscribe.priority,
+			order: subscribe.order,
+			groups: 0,
+			bytes: 0,
+		});
+
+		match subscriptions.entry(subscribe.id) {
+			hash_map::Entry::Occupied(_) => return Err(Error::Duplicate),
+			hash_map::Entry::Vacant(entry) => entry.insert(stats.clone()),
+		};
+
+		Ok(stats)
 	}
 
 	#[tracing::instrument("publishing", skip_all, err, fields(track = ?subscribe.path, id = subscribe.id))]
-	async fn serve_subscribe(&mut self, stream: &mut Stream, subscribe: message::Subscribe) -> Result<(), Error> {
+	async fn serve_subscribe(
+		&mut self,
+		stream: &mut Stream,
+		subscribe: message::Subscribe,
+		stats: Lock<Subscription>,
+	) -> Result<(), Error> {
 		let track = Track {
 			path: subscribe.path,
 			priority: subscribe.priority,
@@ -134,30 +253,172 @@ impl Publisher {
 			latest: track.latest_group(),
 			order: track.order,
 			priority: track.priority,
+			ttl: track.ttl(),
 		};
 
 		tracing::info!(?info, "active");
 
 		stream.writer.encode(&info).await?;
 
+		{
+			let mut stats = stats.lock();
+			stats.priority = track.priority;
+			stats.order = track.order;
+		}
+
+		let observer = self.observer.lock().clone();
+
+		// Groups older than this are dropped, even if we're in the middle of serving them.
+		let ttl = track.ttl();
+
 		let mut tasks = FuturesUnordered::new();
 		let mut complete = false;
 
+		// The current settings, shared with in-flight groups so they can be reprioritized or skipped.
+		let (settings, _) = watch::channel(Settings {
+			priority: track.priority,
+			order: track.order,
+			start: subscribe.start,
+		});
+
+		// Used to check the delivery mode for each group, since the producer can change it at any time.
+		let mode = track.clone();
+
+		let serve = |mut group: GroupConsumer| {
+			let delivery = mode.delivery();
+			let mut session = self.session.clone();
+			let settings = settings.subscribe();
+			let stats = stats.clone();
+			let observer = observer.clone();
+			let counters = self.counters.clone();
+
+			async move {
+				let _in_flight = counters.group();
+
+				let res = async {
+					// Small groups are sent as a datagram, otherwise we fall back to a stream.
+					if delivery == Delivery::Datagram {
+						let datagram =
+							Self::serve_datagram(&mut session, subscribe.id, &stats, &observer, ttl, group.clone());
+						if datagram.await? {
+							counters.datagram_sent();
+							return Ok(());
+						}
+					}
+
+					// TODO open streams in priority order to help with MAX_STREAMS flow control issues.
+					let stream = Writer::open(&mut session, message::DataType::Group).await?;
+					counters.stream_sent();
+
+					Self::serve_group(stream, subscribe.id, settings, stats, observer, ttl, &mut group).await
+				}
+				.await;
+
+				(group, res)
+			}
+		};
+
+		let mut start = subscribe.start;
+		let mut end = subscribe.end;
+
+		// The range of groups we've served so far, used to avoid duplicates when the range is extended.
+		let mut oldest: Option<u64> = None;
+		let mut newest: Option<u64> = None;
+
+		// Serve any requested older groups from the cache first.
+		if let Some(start) = subscribe.start {
+			let upper = end.map_or(Bound::Unbounded, Bound::Included);
+
+			for group in track.cached((Bound::Included(start), upper)) {
+				oldest.get_or_insert(group.sequence);
+				newest = Some(group.sequence);
+				tasks.push(serve(group));
+			}
+
+			// Don't serve any live groups before the start either, ex. if it's ahead of the latest group.
+			track.start_at(start);
+		}
+
+		// Keep serving new groups unless we've already reached the end.
+		let mut live = Self::is_live(end, newest);
+
 		loop {
+			if !live && tasks.is_empty() {
+				break;
+			}
+
 			tokio::select! {
-				Some(group) = track.next_group().transpose() => {
-					let mut group = group?;
-					let session = self.session.clone();
-					let priority = Self::stream_priority(track.priority, track.order, group.sequence);
-
-					tasks.push(async move {
-						let res = Self::serve_group(session, subscribe.id, priority, &mut group).await;
-						(group, res)
-					});
+				Some(event) = track.next().transpose(), if live => {
+					let group = match event? {
+						TrackEvent::Group(group) => group,
+						TrackEvent::Dropped(dropped) => {
+							// Forward any drops from upstream, ex. when relaying, so subscribers don't wait for them.
+							// NOTE: The count on the wire is the number of additional groups, so an empty drop is skipped.
+							if let Some(count) = dropped.count.checked_sub(1) {
+								if end.is_none_or(|end| dropped.sequence <= end) {
+									Self::send_drop(stream, &observer, &stats, dropped.sequence, count, dropped.code).await?;
+								}
+							}
+
+							continue;
+						}
+					};
+
+					// We skipped past the end, so there's nothing more to serve.
+					if end.is_some_and(|end| group.sequence > end) {
+						live = false;
+						continue;
+					}
+
+					oldest.get_or_insert(group.sequence);
+					newest = newest.max(Some(group.sequence));
+					live = Self::is_live(end, newest);
+
+					tasks.push(serve(group));
 				},
 				res = stream.reader.decode_maybe::<message::SubscribeUpdate>(), if !complete => match res? {
-					Some(_update) => {
-						// TODO use it
+					Some(update) => {
+						tracing::debug!(?update, "updated");
+
+						// NOTE: A missing start/end means unchanged, not unbounded.
+						if update.end.is_some() {
+							end = update.end;
+						}
+
+						if let Some(update) = update.start {
+							start = Some(update);
+
+							// Backfill from the cache if the start was moved earlier.
+							let upper = match (oldest, end) {
+								(Some(oldest), Some(end)) if end < oldest => Bound::Included(end),
+								(Some(oldest), _) => Bound::Excluded(oldest),
+								(None, end) => end.map_or(Bound::Unbounded, Bound::Included),
+							};
+
+							for group in track.cached((Bound::Included(update), upper)) {
+								oldest = Some(oldest.map_or(group.sequence, |oldest| oldest.min(group.sequence)));
+								newest = newest.max(Some(group.sequence));
+								tasks.push(serve(group));
+							}
+
+							// Otherwise skip any new groups before the start.
+							track.start_at(update);
+						}
+
+						// Any new and in-flight groups will use the new priority, and stop if they're before the start.
+						settings.send_replace(Settings {
+							priority: update.priority,
+							order: update.order,
+							start,
+						});
+
+						{
+							let mut stats = stats.lock();
+							stats.priority = update.priority;
+							stats.order = update.order;
+						}
+
+						live = Self::is_live(end, newest);
 					},
 					// Subscribe has completed
 					None => {
@@ -167,16 +428,14 @@ impl Publisher {
 				Some(res) = tasks.next() => {
 					let (group, res) = res;
 
+					// Don't bother reporting groups the subscriber no longer wants.
+					if start.is_some_and(|start| group.sequence < start) {
+						continue;
+					}
+
 					if let Err(err) = res {
 						tracing::warn!(?err, subscribe = ?subscribe.id, group = group.sequence, "dropped");
-
-						let drop = message::GroupDrop {
-							sequence: group.sequence,
-							count: 0,
-							code: err.to_code(),
-						};
-
-						stream.writer.encode(&drop).await?;
+						Self::send_drop(stream, &observer, &stats, group.sequence, 0, err.to_code()).await?;
 					}
 				},
 				else => break,
@@ -188,28 +447,153 @@ impl Publisher {
 		Ok(())
 	}
 
-	#[tracing::instrument("group", skip_all, fields(?subscribe, ?priority, sequence = group.sequence))]
+	// Tell the subscriber that groups won't be delivered, where count is the number of additional groups.
+	async fn send_drop(
+		stream: &mut Stream,
+		observer: &Option<Arc<dyn Observer>>,
+		stats: &Lock<Subscription>,
+		sequence: u64,
+		count: u64,
+		code: u32,
+	) -> Result<(), Error> {
+		let drop = message::GroupDrop { sequence, count, code };
+		stream.writer.encode(&drop).await?;
+
+		if let Some(observer) = observer {
+			observer.drop_sent(&stats.lock().path, code);
+		}
+
+		Ok(())
+	}
+
+	// Returns true if there may be more groups to serve before the end.
+	fn is_live(end: Option<u64>, newest: Option<u64>) -> bool {
+		match (end, newest) {
+			(Some(end), Some(newest)) => newest < end,
+			_ => true,
+		}
+	}
+
+	#[tracing::instrument("group", skip_all, fields(?subscribe, sequence = group.sequence))]
 	pub async fn serve_group(
-		mut session: web_transport::Session,
+		mut stream: Writer,
 		subscribe: u64,
-		priority: i32,
+		mut settings: watch::Receiver<Settings>,
+		stats: Lock<Subscription>,
+		observer: Option<Arc<dyn Observer>>,
+		ttl: Option<Duration>,
 		group: &mut GroupConsumer,
 	) -> Result<(), Error> {
-		// TODO open streams in priority order to help with MAX_STREAMS flow control issues.
-		let mut stream = Writer::open(&mut session, message::DataType::Group).await?;
-		stream.set_priority(priority);
+		Self::reprioritize(&mut stream, &mut settings, group.sequence);
 
 		tracing::trace!("serving");
 
-		Self::serve_group_inner(subscribe, group, &mut stream)
-			.await
-			.or_close(&mut stream)
+		let sequence = group.sequence;
+		let mut skip = settings.clone();
+
+		let res = tokio::select! {
+			res = Self::serve_group_inner(subscribe, group, &mut stream, &mut settings, &stats, &observer, ttl) => res,
+			// The subscriber moved the start past this group.
+			Ok(_) = skip.wait_for(|settings| settings.start.is_some_and(|start| sequence < start)) => Err(Error::Cancel),
+		};
+		res.or_close(&mut stream)?;
+
+		let mut stats = stats.lock();
+		stats.groups += 1;
+
+		if let Some(observer) = &observer {
+			observer.group_sent(&stats.path);
+		}
+
+		Ok(())
+	}
+
+	// Send the entire group as a single datagram once it's complete, returning false if it's too large.
+	// NOTE: The group is cloned so we can still fall back to serving it from the start over a stream.
+	async fn serve_datagram(
+		session: &mut web_transport::Session,
+		subscribe: u64,
+		stats: &Lock<Subscription>,
+		observer: &Option<Arc<dyn Observer>>,
+		ttl: Option<Duration>,
+		mut group: GroupConsumer,
+	) -> Result<bool, Error> {
+		let max = session.max_datagram_size().await;
+
+		let mut msg = message::GroupDatagram {
+			subscribe,
+			sequence: group.sequence,
+			frames: Vec::new(),
+		};
+
+		while let Some(mut frame) = group.next_frame().await? {
+			// Give up before reading a frame that can't possibly fit.
+			if frame.size > max {
+				return Ok(false);
+			}
+
+			let payload = frame.read_all().await?;
+			if payload.len() != frame.size {
+				return Err(Error::WrongSize);
+			}
+
+			msg.frames.push(payload);
+
+			if msg.encode_size() > max {
+				return Ok(false);
+			}
+		}
+
+		if ttl.is_some_and(|ttl| group.age() >= ttl) {
+			return Err(Error::Expired);
+		}
+
+		let mut buf = Vec::with_capacity(msg.encode_size());
+		msg.encode(&mut buf);
+
+		// The maximum size can shrink, ex. if the path MTU changes, so we fall back to a stream on error too.
+		if let Err(err) = session.send_datagram(buf.into()).await {
+			tracing::debug!(?err, sequence = group.sequence, "failed to send datagram");
+			return Ok(false);
+		}
+
+		tracing::trace!(sequence = group.sequence, frames = msg.frames.len(), "served datagram");
+
+		let mut stats = stats.lock();
+		stats.groups += 1;
+
+		for frame in &msg.frames {
+			stats.bytes += frame.len() as u64;
+
+			if let Some(observer) = observer {
+				observer.frame_sent(&stats.path, frame.len());
+			}
+		}
+
+		if let Some(observer) = observer {
+			observer.group_sent(&stats.path);
+		}
+
+		Ok(true)
+	}
+
+	// Apply the latest priority and order to the group stream.
+	fn reprioritize(stream: &mut Writer, settings: &mut watch::Receiver<Settings>, sequence: u64) {
+		let Settings { priority, order, .. } = *settings.borrow_and_update();
+		let priority = Self::stream_priority(priority, order, sequence);
+
+		tracing::trace!(?priority, "priority");
+		stream.set_priority(priority);
 	}
 
 	pub async fn serve_group_inner(
 		subscribe: u64,
 		group: &mut GroupConsumer,
 		stream: &mut Writer,
+		settings: &mut watch::Receiver<Settings>,
+		stats: &Lock<Subscription>,
+		observer: &Option<Arc<dyn Observer>>,
+		ttl: Option<Duration>,
 	) -> Result<(), Error> {
 		let msg = message::Group {
 			subscribe,
@@ -221,6 +605,11 @@ impl Publisher {
 		let mut frames = 0;
 
 		while let Some(mut frame) = group.next_frame().await? {
+			// Stop serving a group once it's too old to be useful.
+			if ttl.is_some_and(|ttl| group.age() >= ttl) {
+				return Err(Error::Expired);
+			}
+
 			let header = message::Frame { size: frame.size };
 			stream.encode(&header).await?;
 
@@ -230,13 +619,23 @@ impl Publisher {
 				remain = remain.checked_sub(chunk.len()).ok_or(Error::WrongSize)?;
 				tracing::trace!(chunk = chunk.len(), remain, "chunk");
 
+				// NOTE: We can only reprioritize between writes, not while one is blocked on flow control.
+				if settings.has_changed().unwrap_or(false) {
+					Self::reprioritize(stream, settings, group.sequence);
+				}
+
 				stream.write(&chunk).await?;
+				stats.lock().bytes += chunk.len() as u64;
 			}
 
 			if remain > 0 {
 				return Err(Error::WrongSize);
 			}
 
+			if let Some(observer) = observer {
+				observer.frame_sent(&stats.lock().path, frame.size);
+			}
+
 			frames += 1;
 		}
 
@@ -265,6 +664,7 @@ impl Publisher {
 			latest: track.latest_group(),
 			priority: track.priority,
 			order: track.order,
+			ttl: track.ttl(),
 		};
 
 		stream.writer.encode(&info).await?;
@@ -277,11 +677,24 @@ impl Publisher {
 			return Ok(track.clone());
 		}
 
-		let router = self.router.lock().clone();
-		match router {
-			Some(router) => router.subscribe(track).await,
-			None => Err(Error::NotFound),
+		let routes = self.routes.lock().clone();
+		Self::route_track(&routes, track).await
+	}
+
+	// Try each router that matches the path, falling through to the next on NotFound.
+	async fn route_track(routes: &[(Filter, RouterConsumer)], track: Track) -> Result<TrackConsumer, Error> {
+		let routes = routes
+			.iter()
+			.filter(|(filter, _)| filter.matches(&track.path).is_some());
+
+		for (_, router) in routes {
+			match router.subscribe(track.clone()).await {
+				Err(Error::NotFound) => continue,
+				res => return res,
+			}
 		}
+
+		Err(Error::NotFound)
 	}
 
 	// Quinn takes a i32 priority.
@@ -328,4 +741,74 @@ mod test {
 		assert(1, GroupOrder::Desc, 50, 2 * U24 - 49);
 		assert(1, GroupOrder::Desc, 0, 2 * U24 + 1);
 	}
+
+	#[test]
+	fn route_fallthrough() {
+		use crate::Router;
+		use futures::{executor::block_on, future::join};
+
+		let (mut generator, generated) = Router::default().produce();
+		let (mut upstream, relayed) = Router::default().produce();
+
+		let routes = vec![(Filter::Prefix("generate/".into()), generated), (Filter::Any, relayed)];
+
+		let respond = async {
+			// The generator doesn't know this path, so it falls through to the upstream.
+			let request = generator.requested().await.unwrap();
+			assert_eq!(request.path, "generate/unknown");
+			request.close(Error::NotFound);
+
+			let request = upstream.requested().await.unwrap();
+			assert_eq!(request.path, "generate/unknown");
+			request.produce()
+		};
+
+		let (res, _track) = block_on(join(
+			Publisher::route_track(&routes, Track::new("generate/unknown")),
+			respond,
+		));
+		assert_eq!(res.unwrap().path, "generate/unknown");
+
+		// Paths that don't match the filter skip the generator entirely.
+		let respond = async {
+			let request = upstream.requested().await.unwrap();
+			assert_eq!(request.path, "other");
+			request.close(Error::NotFound);
+		};
+
+		let (res, _) = block_on(join(Publisher::route_track(&routes, Track::new("other")), respond));
+		assert!(matches!(res, Err(Error::NotFound)));
+	}
+
+	#[test]
+	fn reserve_subscriptions() {
+		let subscriptions = Lock::default();
+
+		let subscribe = |id| message::Subscribe {
+			id,
+			path: "test".to_string(),
+			priority: 0,
+			order: GroupOrder::Desc,
+			start: None,
+			end: None,
+		};
+
+		// The slot is taken immediately, before the track is resolved.
+		assert!(Publisher::reserve(&subscriptions, Some(1), &subscribe(1)).is_ok());
+		assert!(matches!(
+			Publisher::reserve(&subscriptions, Some(1), &subscribe(2)),
+			Err(Error::LimitExceeded)
+		));
+
+		// The slot is released when the subscription is done.
+		subscriptions.lock().remove(&1);
+		assert!(Publisher::reserve(&subscriptions, Some(1), &subscribe(2)).is_ok());
+
+		// Reusing an ID doesn't replace the active subscription.
+		assert!(matches!(
+			Publisher::reserve(&subscriptions, None, &subscribe(2)),
+			Err(Error::Duplicate)
+		));
+		assert_eq!(subscriptions.lock().len(), 1);
+	}
 }
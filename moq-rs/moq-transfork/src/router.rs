@@ -67,6 +67,11 @@ impl RouterConsumer {
 	pub async fn closed(&self) {
 		self.queue.closed().await;
 	}
+
+	/// The number of requests waiting to be handled by the [RouterProducer].
+	pub fn queued(&self) -> usize {
+		self.queue.max_capacity() - self.queue.capacity()
+	}
 }
 
 /// An outstanding request for a path.
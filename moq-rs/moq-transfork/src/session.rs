@@ -1,9 +1,12 @@
+use std::sync::Arc;
+
 use crate::{
-	AnnouncedConsumer, Error, Filter, Publisher, Reader, RouterConsumer, Stream, Subscriber, Track, TrackConsumer,
+	AnnouncedConsumer, ConnectionStats, Counters, Error, Filter, Observer, Publisher, Reader, Retention,
+	RouterConsumer, SessionStats, Stream, Subscriber, Subscription, Track, TrackConsumer,
 };
 use moq_proto::message;
 
-use moq_async::{spawn, OrClose};
+use moq_async::{spawn, Lock, OrClose};
 
 /// A MoqTransfork session, used to publish and/or subscribe to broadcasts.
 ///
@@ -14,24 +17,32 @@ pub struct Session {
 	webtransport: web_transport::Session,
 	publisher: Publisher,
 	subscriber: Subscriber,
+
+	counters: Arc<Counters>,
+	connection: Lock<Option<Arc<dyn Fn() -> ConnectionStats + Send + Sync>>>,
 }
 
 impl Session {
 	fn new(mut session: web_transport::Session, stream: Stream) -> Self {
-		let publisher = Publisher::new(session.clone());
-		let subscriber = Subscriber::new(session.clone());
+		let counters = Arc::new(Counters::default());
+
+		let publisher = Publisher::new(session.clone(), counters.clone());
+		let subscriber = Subscriber::new(session.clone(), counters.clone());
 
 		let this = Self {
 			webtransport: session.clone(),
 			publisher: publisher.clone(),
 			subscriber: subscriber.clone(),
+			counters: counters.clone(),
+			connection: Default::default(),
 		};
 
 		spawn(async move {
 			let res = tokio::select! {
 				res = Self::run_session(stream) => res,
-				res = Self::run_bi(session.clone(), publisher) => res,
-				res = Self::run_uni(session.clone(), subscriber) => res,
+				res = Self::run_bi(session.clone(), publisher, counters.clone()) => res,
+				res = Self::run_uni(session.clone(), subscriber.clone(), counters) => res,
+				res = Self::run_datagrams(session.clone(), subscriber) => res,
 			};
 
 			if let Err(err) = res {
@@ -48,7 +59,11 @@ impl Session {
 		let mut session = session.into();
 		let mut stream = Stream::open(&mut session, message::ControlType::Session).await?;
 		Self::connect_setup(&mut stream).await.or_close(&mut stream)?;
-		Ok(Self::new(session, stream))
+
+		let this = Self::new(session, stream);
+		this.counters.stream_sent();
+
+		Ok(this)
 	}
 
 	async fn connect_setup(setup: &mut Stream) -> Result<(), Error> {
@@ -76,7 +91,11 @@ impl Session {
 		}
 
 		Self::accept_setup(&mut stream).await.or_close(&mut stream)?;
-		Ok(Self::new(session, stream))
+
+		let this = Self::new(session, stream);
+		this.counters.stream_received();
+
+		Ok(this)
 	}
 
 	async fn accept_setup(control: &mut Stream) -> Result<(), Error> {
@@ -103,9 +122,15 @@ impl Session {
 		Err(Error::Cancel)
 	}
 
-	async fn run_uni(mut session: web_transport::Session, subscriber: Subscriber) -> Result<(), Error> {
+	async fn run_uni(
+		mut session: web_transport::Session,
+		subscriber: Subscriber,
+		counters: Arc<Counters>,
+	) -> Result<(), Error> {
 		loop {
 			let mut stream = Reader::accept(&mut session).await?;
+			counters.stream_received();
+
 			let subscriber = subscriber.clone();
 
 			spawn(async move {
@@ -114,6 +139,17 @@ impl Session {
 		}
 	}
 
+	async fn run_datagrams(mut session: web_transport::Session, mut subscriber: Subscriber) -> Result<(), Error> {
+		loop {
+			let datagram = session.recv_datagram().await?;
+
+			// NOTE: Datagrams are unreliable, so a bad or late datagram (ex. after unsubscribing) is just ignored.
+			if let Err(err) = subscriber.recv_datagram(datagram) {
+				tracing::debug!(?err, "ignoring datagram");
+			}
+		}
+	}
+
 	async fn run_data(stream: &mut Reader, mut subscriber: Subscriber) -> Result<(), Error> {
 		let kind = stream.decode().await?;
 		match kind {
@@ -121,9 +157,15 @@ impl Session {
 		}
 	}
 
-	async fn run_bi(mut session: web_transport::Session, publisher: Publisher) -> Result<(), Error> {
+	async fn run_bi(
+		mut session: web_transport::Session,
+		publisher: Publisher,
+		counters: Arc<Counters>,
+	) -> Result<(), Error> {
 		loop {
 			let mut stream = Stream::accept(&mut session).await?;
+			counters.stream_received();
+
 			let publisher = publisher.clone();
 
 			spawn(async move {
@@ -161,8 +203,17 @@ impl Session {
 	/// Optionally route unknown paths.
 	///
 	/// This is advanced functionality if you wish to perform dynamic track generation in conjunction with [Self::announce].
+	/// This is equivalent to [Self::route_filter] with [Filter::Any].
 	pub fn route(&mut self, router: RouterConsumer) {
-		self.publisher.route(router);
+		self.publisher.route(Filter::Any, router);
+	}
+
+	/// Optionally route unknown paths matching the filter, ex. a prefix served by a transcoder.
+	///
+	/// Routers are tried in the order they were added, falling through to the next if one returns [Error::NotFound].
+	/// For example, route `transcode/` to a generator before routing everything else to an upstream relay.
+	pub fn route_filter(&mut self, filter: Filter, router: RouterConsumer) {
+		self.publisher.route(filter, router);
 	}
 
 	/// Subscribe to a track and start receiving data over the network.
@@ -170,11 +221,68 @@ impl Session {
 		self.subscriber.subscribe(track)
 	}
 
+	/// Returns the subscriptions we're currently serving to the remote, including how much has been sent.
+	pub fn subscriptions(&self) -> Vec<Subscription> {
+		self.publisher.subscriptions()
+	}
+
+	/// Change the priority and group order of an active subscription, without resubscribing.
+	///
+	/// The subscription is identified by [Track::path] and shared by all consumers of that track.
+	/// A start or end of None is left unchanged. An earlier start is backfilled from the publisher's cache, while a later start skips older groups.
+	pub fn update(&self, track: Track, start: Option<u64>, end: Option<u64>) -> Result<(), Error> {
+		self.subscriber.update(track, start, end)
+	}
+
+	/// Keep older groups around for any tracks we subscribe to, instead of just the latest.
+	///
+	/// This allows downstream subscribers to request older groups via the subscribe start/end range, ex. when they join late or reconnect.
+	pub fn set_retention(&mut self, retention: Retention) {
+		self.subscriber.set_retention(retention);
+	}
+
+	/// Reject subscriptions from the remote with [Error::LimitExceeded] once this many are active.
+	pub fn set_max_subscriptions(&mut self, max: usize) {
+		self.publisher.set_max_subscriptions(max);
+	}
+
+	/// Get a callback for every group and frame sent or received, ex. to export metrics.
+	pub fn set_observer(&mut self, observer: Arc<dyn Observer>) {
+		self.publisher.set_observer(observer.clone());
+		self.subscriber.set_observer(observer);
+	}
+
+	/// Provide statistics for the underlying QUIC connection, which are included in [Self::stats].
+	///
+	/// The generic WebTransport session doesn't expose them, so this is up to the QUIC implementation (ex. `moq_native::quic::stats`).
+	pub fn set_connection_stats<F: Fn() -> ConnectionStats + Send + Sync + 'static>(&mut self, stats: F) {
+		self.connection.lock().replace(Arc::new(stats));
+	}
+
+	/// Returns a snapshot of the session's statistics, ex. to show the connection quality.
+	pub fn stats(&self) -> SessionStats {
+		let connection = self.connection.lock().clone();
+
+		SessionStats {
+			connection: connection.map(|stats| stats()),
+			serving: self.publisher.subscriptions().len(),
+			subscribed: self.subscriber.subscribed(),
+			..self.counters.snapshot()
+		}
+	}
+
 	/// Discover any tracks published by the remote matching a (wildcard) filter.
 	pub fn announced(&self, filter: Filter) -> AnnouncedConsumer {
 		self.subscriber.announced(filter)
 	}
 
+	/// Tell the remote that we won't use a track it announced, without affecting the rest of the session.
+	///
+	/// The error is sent over any [Self::announced] streams matching the path, and reported to the remote's [Observer].
+	pub fn reject_announce(&self, path: &str, err: Error) {
+		self.subscriber.reject_announce(path, err)
+	}
+
 	/// Close the underlying WebTransport session.
 	pub fn close(mut self, err: Error) {
 		self.webtransport.close(err.to_code(), &err.to_string());
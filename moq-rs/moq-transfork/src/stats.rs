@@ -0,0 +1,127 @@
+use std::{
+	sync::{
+		atomic::{AtomicU64, AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+/// Statistics for the underlying QUIC connection, provided by the QUIC implementation (ex. quinn).
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionStats {
+	/// The current smoothed round-trip time.
+	pub rtt: Duration,
+
+	/// The current congestion window, in bytes.
+	pub cwnd: u64,
+
+	/// UDP bytes, including QUIC overhead and retransmissions.
+	pub bytes_sent: u64,
+	pub bytes_received: u64,
+
+	/// The number of packets that were deemed lost.
+	pub lost_packets: u64,
+}
+
+/// A snapshot of a session's statistics, returned by [crate::Session::stats].
+#[derive(Clone, Debug, Default)]
+pub struct SessionStats {
+	/// Only available if provided via [crate::Session::set_connection_stats], ex. not in the browser.
+	pub connection: Option<ConnectionStats>,
+
+	/// The number of streams opened by us and by the remote.
+	pub streams_sent: u64,
+	pub streams_received: u64,
+
+	/// The number of groups sent and received as datagrams, see [crate::Delivery::Datagram].
+	pub datagrams_sent: u64,
+	pub datagrams_received: u64,
+
+	/// The number of subscriptions we're serving to the remote.
+	pub serving: usize,
+
+	/// The number of our subscriptions to the remote.
+	pub subscribed: usize,
+
+	/// The number of groups currently being sent or received.
+	pub groups_in_flight: usize,
+}
+
+// Counters shared by the publisher and subscriber.
+#[derive(Default)]
+pub(crate) struct Counters {
+	streams_sent: AtomicU64,
+	streams_received: AtomicU64,
+	datagrams_sent: AtomicU64,
+	datagrams_received: AtomicU64,
+	groups_in_flight: AtomicUsize,
+}
+
+impl Counters {
+	pub fn stream_sent(&self) {
+		self.streams_sent.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn stream_received(&self) {
+		self.streams_received.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn datagram_sent(&self) {
+		self.datagrams_sent.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn datagram_received(&self) {
+		self.datagrams_received.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Count a group as in flight until the guard is dropped.
+	pub fn group(self: &Arc<Self>) -> InFlight {
+		self.groups_in_flight.fetch_add(1, Ordering::Relaxed);
+		InFlight(self.clone())
+	}
+
+	pub fn snapshot(&self) -> SessionStats {
+		SessionStats {
+			streams_sent: self.streams_sent.load(Ordering::Relaxed),
+			streams_received: self.streams_received.load(Ordering::Relaxed),
+			datagrams_sent: self.datagrams_sent.load(Ordering::Relaxed),
+			datagrams_received: self.datagrams_received.load(Ordering::Relaxed),
+			groups_in_flight: self.groups_in_flight.load(Ordering::Relaxed),
+			..Default::default()
+		}
+	}
+}
+
+pub(crate) struct InFlight(Arc<Counters>);
+
+impl Drop for InFlight {
+	fn drop(&mut self) {
+		self.0.groups_in_flight.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn in_flight() {
+		let counters = Arc::new(Counters::default());
+		counters.stream_sent();
+		counters.stream_received();
+		counters.stream_received();
+
+		let first = counters.group();
+		let second = counters.group();
+		assert_eq!(counters.snapshot().groups_in_flight, 2);
+
+		drop(first);
+		let stats = counters.snapshot();
+		assert_eq!(stats.groups_in_flight, 1);
+		assert_eq!(stats.streams_sent, 1);
+		assert_eq!(stats.streams_received, 2);
+
+		drop(second);
+		assert_eq!(counters.snapshot().groups_in_flight, 0);
+	}
+}
@@ -3,10 +3,15 @@ use std::{
 	sync::{atomic, Arc},
 };
 
-use crate::{AnnouncedConsumer, AnnouncedProducer, Error, Filter, Reader, Stream, Track, TrackConsumer, TrackProducer};
+use crate::{
+	AnnouncedConsumer, AnnouncedProducer, Counters, Delivery, Dropped, Error, Filter, Observer, Reader, Retention,
+	Stream, Track, TrackConsumer, TrackProducer,
+};
 
+use bytes::Bytes;
 use moq_async::{spawn, Lock, OrClose};
-use moq_proto::message;
+use moq_proto::{coding::Decode, message};
+use tokio::sync::{mpsc, watch};
 
 #[derive(Clone)]
 pub(super) struct Subscriber {
@@ -14,36 +19,74 @@ pub(super) struct Subscriber {
 
 	tracks: Lock<HashMap<String, TrackProducer>>,
 	subscribes: Lock<HashMap<u64, TrackProducer>>,
+
+	// Used to change the priority/order of active subscriptions, by path.
+	updates: Lock<HashMap<String, watch::Sender<message::SubscribeUpdate>>>,
 	next_id: Arc<atomic::AtomicU64>,
+
+	// Used to send errors for announced paths, for each announce stream.
+	rejects: Lock<Vec<(Filter, mpsc::UnboundedSender<message::AnnounceError>)>>,
+
+	// How many older groups to keep for each subscribed track.
+	retention: Lock<Retention>,
+
+	observer: Lock<Option<Arc<dyn Observer>>>,
+	counters: Arc<Counters>,
 }
 
 impl Subscriber {
-	pub fn new(session: web_transport::Session) -> Self {
+	pub fn new(session: web_transport::Session, counters: Arc<Counters>) -> Self {
 		Self {
 			session,
 
 			tracks: Default::default(),
 			subscribes: Default::default(),
+			updates: Default::default(),
 			next_id: Default::default(),
+			rejects: Default::default(),
+			retention: Default::default(),
+			observer: Default::default(),
+			counters,
 		}
 	}
 
+	/// The number of our subscriptions that are active.
+	pub fn subscribed(&self) -> usize {
+		self.subscribes.lock().len()
+	}
+
+	pub fn set_retention(&mut self, retention: Retention) {
+		*self.retention.lock() = retention;
+	}
+
+	pub fn set_observer(&mut self, observer: Arc<dyn Observer>) {
+		self.observer.lock().replace(observer);
+	}
+
 	/// Discover any tracks matching a filter.
 	pub fn announced(&self, filter: Filter) -> AnnouncedConsumer {
 		let producer = AnnouncedProducer::default();
 		let consumer = producer.subscribe(filter.clone());
 
+		let (reject, rejects) = mpsc::unbounded_channel();
+		self.rejects.lock().push((filter.clone(), reject));
+
 		let mut session = self.session.clone();
+		let counters = self.counters.clone();
+
 		spawn(async move {
 			let mut stream = match Stream::open(&mut session, message::ControlType::Announce).await {
-				Ok(stream) => stream,
+				Ok(stream) => {
+					counters.stream_sent();
+					stream
+				}
 				Err(err) => {
 					tracing::warn!(?err, "failed to open announce stream");
 					return;
 				}
 			};
 
-			if let Err(err) = Self::run_announce(&mut stream, filter, producer)
+			if let Err(err) = Self::run_announce(&mut stream, filter, producer, rejects)
 				.await
 				.or_close(&mut stream)
 			{
@@ -54,7 +97,30 @@ impl Subscriber {
 		consumer
 	}
 
-	async fn run_announce(stream: &mut Stream, filter: Filter, mut announced: AnnouncedProducer) -> Result<(), Error> {
+	/// Tell the publisher that we won't use an announced path.
+	pub fn reject_announce(&self, path: &str, err: Error) {
+		let mut rejects = self.rejects.lock();
+
+		// Forget about any announce streams that have finished.
+		rejects.retain(|(_, reject)| !reject.is_closed());
+
+		for (filter, reject) in rejects.iter() {
+			if let Some(m) = filter.matches(path) {
+				let msg = message::AnnounceError {
+					suffix: m.capture().to_string(),
+					code: err.to_code(),
+				};
+				reject.send(msg).ok();
+			}
+		}
+	}
+
+	async fn run_announce(
+		stream: &mut Stream,
+		filter: Filter,
+		mut announced: AnnouncedProducer,
+		mut rejects: mpsc::UnboundedReceiver<message::AnnounceError>,
+	) -> Result<(), Error> {
 		stream
 			.writer
 			.encode(&message::AnnouncePlease { filter: filter.clone() })
@@ -72,6 +138,10 @@ impl Subscriber {
 						None => return Ok(()),
 					}
 				},
+				Some(reject) = rejects.recv() => {
+					tracing::debug!(?reject, "rejecting announce");
+					stream.writer.encode(&reject).await?;
+				},
 				// Stop if the consumer is no longer interested
 				_ = announced.closed() => return Ok(()),
 			}
@@ -108,6 +178,7 @@ impl Subscriber {
 	pub fn subscribe(&self, track: Track) -> TrackConsumer {
 		let path = track.path.clone();
 		let (writer, reader) = track.clone().produce();
+		writer.set_retention(*self.retention.lock());
 
 		// Check if we can deduplicate this subscription
 		match self.tracks.lock().entry(path.clone()) {
@@ -115,25 +186,60 @@ impl Subscriber {
 			hash_map::Entry::Vacant(entry) => entry.insert(writer.clone()),
 		};
 
+		let (update, updates) = watch::channel(message::SubscribeUpdate {
+			priority: track.priority,
+			order: track.order,
+			start: None,
+			end: None,
+		});
+		self.updates.lock().insert(path.clone(), update);
+
 		let mut this = self.clone();
 		let id = self.next_id.fetch_add(1, atomic::Ordering::Relaxed);
 
 		spawn(async move {
 			if let Ok(mut stream) = Stream::open(&mut this.session, message::ControlType::Subscribe).await {
-				if let Err(err) = this.run_subscribe(id, writer, &mut stream).await.or_close(&mut stream) {
+				this.counters.stream_sent();
+
+				if let Err(err) = this
+					.run_subscribe(id, writer, updates, &mut stream)
+					.await
+					.or_close(&mut stream)
+				{
 					tracing::warn!(?err, "subscribe error");
 				}
 			}
 
 			this.subscribes.lock().remove(&id);
 			this.tracks.lock().remove(&path);
+			this.updates.lock().remove(&path);
 		});
 
 		reader
 	}
 
+	/// Change the priority, group order and range of an active subscription.
+	pub fn update(&self, track: Track, start: Option<u64>, end: Option<u64>) -> Result<(), Error> {
+		let updates = self.updates.lock();
+		let update = updates.get(&track.path).ok_or(Error::NotFound)?;
+		update.send_replace(message::SubscribeUpdate {
+			priority: track.priority,
+			order: track.order,
+			start,
+			end,
+		});
+
+		Ok(())
+	}
+
 	#[tracing::instrument("subscribe", skip_all, fields(?id, track = ?track.path))]
-	async fn run_subscribe(&mut self, id: u64, track: TrackProducer, stream: &mut Stream) -> Result<(), Error> {
+	async fn run_subscribe(
+		&mut self,
+		id: u64,
+		track: TrackProducer,
+		mut updates: watch::Receiver<message::SubscribeUpdate>,
+		stream: &mut Stream,
+	) -> Result<(), Error> {
 		self.subscribes.lock().insert(id, track.clone());
 
 		let request = message::Subscribe {
@@ -154,18 +260,36 @@ impl Subscriber {
 
 		tracing::info!(?info, "active");
 
+		// Expire groups the same way as the publisher, ex. when relaying.
+		track.set_ttl(info.ttl);
+
 		loop {
 			tokio::select! {
 				res = stream.reader.decode_maybe::<message::GroupDrop>() => {
 					match res? {
 						Some(drop) => {
 							tracing::info!(?drop, "dropped");
-							// TODO expose updates to application
-							// TODO use to detect gaps
+
+							if let Some(observer) = self.observer.lock().as_ref() {
+								observer.drop_received(&track.path, drop.code);
+							}
+
+							// NOTE: The count on the wire is the number of additional groups dropped.
+							track.drop_groups(Dropped {
+								sequence: drop.sequence,
+								count: drop.count + 1,
+								code: drop.code,
+							});
 						},
 						None => break,
 					}
 				}
+				Ok(_) = updates.changed() => {
+					let update = updates.borrow_and_update().clone();
+
+					tracing::debug!(?update, "updating");
+					stream.writer.encode(&update).await?;
+				}
 				// Close when there are no more subscribers
 				_ = track.unused() => break
 			};
@@ -177,19 +301,23 @@ impl Subscriber {
 	}
 
 	pub async fn recv_group(&mut self, stream: &mut Reader) -> Result<(), Error> {
+		let _in_flight = self.counters.group();
+
 		let group = stream.decode().await?;
 		self.recv_group_inner(stream, group).await.or_close(stream)
 	}
 
 	#[tracing::instrument("group", skip_all, err, fields(subscribe = ?group.subscribe, group = group.sequence))]
 	pub async fn recv_group_inner(&mut self, stream: &mut Reader, group: message::Group) -> Result<(), Error> {
-		let mut group = {
+		let (path, mut group) = {
 			let mut subs = self.subscribes.lock();
 			let track = subs.get_mut(&group.subscribe).ok_or(Error::Cancel)?;
 
-			track.create_group(group.sequence)
+			(track.path.clone(), track.create_group(group.sequence))
 		};
 
+		let observer = self.observer.lock().clone();
+
 		while let Some(frame) = stream.decode_maybe::<message::Frame>().await? {
 			let mut frame = group.create_frame(frame.size);
 			let mut remain = frame.size;
@@ -202,6 +330,49 @@ impl Subscriber {
 
 				frame.write(chunk);
 			}
+
+			if let Some(observer) = &observer {
+				observer.frame_received(&path, frame.size);
+			}
+		}
+
+		if let Some(observer) = &observer {
+			observer.group_received(&path);
+		}
+
+		Ok(())
+	}
+
+	/// Receive an entire group sent as a datagram, see [Delivery::Datagram].
+	pub fn recv_datagram(&mut self, mut datagram: Bytes) -> Result<(), Error> {
+		let msg = message::GroupDatagram::decode(&mut datagram)?;
+
+		let (path, mut group) = {
+			let mut subs = self.subscribes.lock();
+			let track = subs.get_mut(&msg.subscribe).ok_or(Error::Cancel)?;
+
+			// Deliver the track the same way, ex. when relaying.
+			track.set_delivery(Delivery::Datagram);
+
+			(track.path.clone(), track.create_group(msg.sequence))
+		};
+
+		self.counters.datagram_received();
+		tracing::trace!(subscribe = ?msg.subscribe, group = msg.sequence, frames = msg.frames.len(), "datagram");
+
+		let observer = self.observer.lock().clone();
+
+		for frame in msg.frames {
+			let size = frame.len();
+			group.write_frame(frame);
+
+			if let Some(observer) = &observer {
+				observer.frame_received(&path, size);
+			}
+		}
+
+		if let Some(observer) = &observer {
+			observer.group_received(&path);
 		}
 
 		Ok(())
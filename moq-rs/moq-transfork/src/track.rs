@@ -12,13 +12,19 @@
 //!
 //! The track is closed with [Error] when all writers or readers are dropped.
 
-use tokio::sync::watch;
+use tokio::{sync::watch, time::Instant};
 
 use super::{Group, GroupConsumer, GroupProducer};
 use crate::Error;
 pub use moq_proto::message::GroupOrder;
 
-use std::{cmp::Ordering, ops, sync::Arc};
+use std::{
+	cmp::Ordering,
+	collections::{BTreeSet, VecDeque},
+	ops::{self, RangeBounds},
+	sync::Arc,
+	time::Duration,
+};
 
 /// A track, a collection of indepedent groups (streams) with a specified order/priority.
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -115,16 +121,190 @@ impl From<TrackBuilder> for Track {
 	}
 }
 
+/// Limits on how many older groups a track keeps around, in addition to the latest group.
+///
+/// Groups are evicted oldest first once any of the limits is exceeded.
+/// The default keeps nothing but the latest group.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Retention {
+	/// The maximum number of older groups.
+	pub groups: usize,
+
+	/// The maximum size of all older groups combined, in bytes.
+	pub bytes: Option<usize>,
+
+	/// The maximum time since a group was replaced as the latest.
+	pub age: Option<Duration>,
+}
+
+/// How groups are delivered over the network.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Delivery {
+	/// Each group is sent over its own QUIC stream.
+	#[default]
+	Stream,
+
+	/// Each group is sent as a single QUIC datagram once it's complete, ex. a tiny audio packet or timestamp.
+	///
+	/// Groups that don't fit in a datagram fall back to a stream.
+	/// Datagrams are never retransmitted, so a lost group is simply skipped by consumers.
+	Datagram,
+}
+
+// How many evicted sequences to remember, so consumers can tell how many groups they skipped.
+const MAX_EVICTED: usize = 1024;
+
+// How many drops to remember, for consumers that haven't read them yet.
+const MAX_DROPPED: usize = 1024;
+
+/// Consecutive groups that won't be delivered, ex. because they expired or the publisher reset the stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dropped {
+	/// The first sequence that was dropped.
+	pub sequence: u64,
+
+	/// The number of groups dropped, at least 1.
+	pub count: u64,
+
+	/// The reason, see [Error::to_code].
+	pub code: u32,
+}
+
+/// Returned by [TrackConsumer::next].
+#[derive(Debug)]
+pub enum TrackEvent {
+	/// The next group in the track.
+	Group(GroupConsumer),
+
+	/// Groups that will never be delivered (or were only partially delivered), so the gap can be concealed immediately.
+	Dropped(Dropped),
+}
+
+#[derive(Debug)]
+struct Cached {
+	group: GroupConsumer,
+	expires: Option<Instant>,
+}
+
+impl Cached {
+	fn expired(&self, now: Instant) -> bool {
+		self.expires.is_some_and(|expires| expires <= now)
+	}
+}
+
 #[derive(Debug)]
 struct TrackState {
 	latest: Option<GroupConsumer>,
+
+	// Older groups, ordered by sequence.
+	cache: VecDeque<Cached>,
+	retention: Retention,
+
+	// Groups older than this are never returned or cached.
+	ttl: Option<Duration>,
+
+	delivery: Delivery,
+
+	// The most recently evicted sequences, and how many groups were ever evicted.
+	evicted: VecDeque<u64>,
+	evicted_total: u64,
+
+	// The most recent drops, and how many were ever reported.
+	dropped: VecDeque<Dropped>,
+	dropped_total: u64,
+
 	closed: Result<(), Error>,
 }
 
+impl TrackState {
+	// Every group that can still be returned, including the latest.
+	fn available(&self, now: Instant) -> impl Iterator<Item = &GroupConsumer> {
+		let ttl = self.ttl;
+
+		self.cache
+			.iter()
+			.filter(move |cached| !cached.expired(now))
+			.map(|cached| &cached.group)
+			.chain(self.latest.as_ref())
+			.filter(move |group| ttl.is_none_or(|ttl| group.age() < ttl))
+	}
+
+	fn cache(&mut self, group: GroupConsumer) {
+		let index = self
+			.cache
+			.partition_point(|cached| cached.group.sequence < group.sequence);
+		if self
+			.cache
+			.get(index)
+			.is_some_and(|cached| cached.group.sequence == group.sequence)
+		{
+			return;
+		}
+
+		let expires = self.retention.age.map(|age| Instant::now() + age);
+		self.cache.insert(index, Cached { group, expires });
+
+		self.evict();
+	}
+
+	fn evict(&mut self) {
+		let now = Instant::now();
+		let ttl = self.ttl;
+
+		let mut evicted = Vec::new();
+
+		self.cache.retain(|cached| {
+			let expired = cached.expired(now) || ttl.is_some_and(|ttl| cached.group.age() >= ttl);
+			if expired {
+				evicted.push(cached.group.sequence);
+			}
+
+			!expired
+		});
+
+		while self.cache.len() > self.retention.groups {
+			let oldest = self.cache.pop_front().unwrap();
+			evicted.push(oldest.group.sequence);
+		}
+
+		if let Some(max) = self.retention.bytes {
+			let mut bytes: usize = self.cache.iter().map(|cached| cached.group.size()).sum();
+			while bytes > max {
+				let oldest = self.cache.pop_front().expect("bytes without groups");
+				bytes -= oldest.group.size();
+				evicted.push(oldest.group.sequence);
+			}
+		}
+
+		for sequence in evicted {
+			self.evicted.push_back(sequence);
+			self.evicted_total += 1;
+		}
+
+		while self.evicted.len() > MAX_EVICTED {
+			self.evicted.pop_front();
+		}
+	}
+
+	// The oldest sequence that hasn't been evicted yet, even if it's expired.
+	fn oldest(&self) -> Option<u64> {
+		let oldest = self.cache.front().map(|cached| &cached.group).or(self.latest.as_ref());
+		oldest.map(|group| group.sequence)
+	}
+}
+
 impl Default for TrackState {
 	fn default() -> Self {
 		Self {
 			latest: None,
+			cache: VecDeque::new(),
+			retention: Retention::default(),
+			ttl: None,
+			delivery: Delivery::default(),
+			evicted: VecDeque::new(),
+			evicted_total: 0,
+			dropped: VecDeque::new(),
+			dropped_total: 0,
 			closed: Ok(()),
 		}
 	}
@@ -150,13 +330,21 @@ impl TrackProducer {
 		self.state.send_if_modified(|state| {
 			if let Some(latest) = &state.latest {
 				match reader.sequence.cmp(&latest.sequence) {
-					Ordering::Less => return false,  // Not modified,
+					Ordering::Less => {
+						// A late group can still be useful to anybody fetching older groups, or consumers that haven't seen it.
+						let retained = state.retention.groups > 0;
+						state.cache(reader);
+						return retained;
+					}
 					Ordering::Equal => return false, // TODO error?
 					Ordering::Greater => (),
 				}
 			}
 
-			state.latest = Some(reader);
+			if let Some(prev) = state.latest.replace(reader) {
+				state.cache(prev);
+			}
+
 			true
 		});
 
@@ -176,6 +364,52 @@ impl TrackProducer {
 		self.create_group(sequence)
 	}
 
+	/// Keep older groups around according to the given limits, so late joiners can fetch them.
+	pub fn set_retention(&self, retention: Retention) {
+		self.state.send_if_modified(|state| {
+			state.retention = retention;
+			state.evict();
+			false
+		});
+	}
+
+	/// Drop any groups older than the TTL, ex. because old audio is worthless.
+	///
+	/// Expired groups are not returned to consumers, and any in-flight groups are dropped with [Error::Expired].
+	pub fn set_ttl(&self, ttl: Option<Duration>) {
+		self.state.send_if_modified(|state| {
+			state.ttl = ttl;
+			state.evict();
+			false
+		});
+	}
+
+	/// Change how groups are delivered to any subscribers, ex. [Delivery::Datagram] for tiny groups.
+	pub fn set_delivery(&self, delivery: Delivery) {
+		self.state.send_if_modified(|state| {
+			state.delivery = delivery;
+			false
+		});
+	}
+
+	/// Tell consumers that some groups won't be delivered, ex. when the publisher reported a [Dropped].
+	///
+	/// A drop with a count of 0 is ignored.
+	pub fn drop_groups(&self, dropped: Dropped) {
+		if dropped.count == 0 {
+			return;
+		}
+
+		self.state.send_modify(|state| {
+			state.dropped.push_back(dropped);
+			state.dropped_total += 1;
+
+			if state.dropped.len() > MAX_DROPPED {
+				state.dropped.pop_front();
+			}
+		});
+	}
+
 	/// Close the track with an error.
 	pub fn close(self, err: Error) {
 		self.state.send_modify(|state| {
@@ -203,61 +437,229 @@ impl ops::Deref for TrackProducer {
 }
 
 /// A consumer for a track, used to read groups.
+///
+/// By default, a consumer starts at the latest group and then returns every newer group that's still retained, in the track's [GroupOrder].
 #[derive(Clone, Debug)]
 pub struct TrackConsumer {
 	pub info: Arc<Track>,
 	state: watch::Receiver<TrackState>,
-	prev: Option<u64>, // The previous sequence number
+
+	// The oldest sequence we'll return, defaulting to the latest group when first read.
+	start: Option<u64>,
+
+	// Sequences already returned, pruned once they're evicted.
+	returned: BTreeSet<u64>,
+
+	// The number of evicted groups we've checked, and how many of them we skipped.
+	evicted: u64,
+	skipped: u64,
+
+	// The number of drops we've returned.
+	dropped: u64,
 }
 
 impl TrackConsumer {
 	fn new(state: watch::Receiver<TrackState>, info: Arc<Track>) -> Self {
+		let (evicted, dropped) = {
+			let state = state.borrow();
+			(state.evicted_total, state.dropped_total)
+		};
+
 		Self {
 			state,
 			info,
-			prev: None,
+			start: None,
+			returned: BTreeSet::new(),
+			evicted,
+			skipped: 0,
+			dropped,
 		}
 	}
 
+	/// Start returning groups from the given sequence instead of the latest group.
+	///
+	/// Only groups still retained by the producer (see [Retention]) can be returned.
+	pub fn start_at(&mut self, sequence: u64) {
+		self.start = Some(sequence);
+	}
+
 	pub fn get_group(&self, sequence: u64) -> Result<GroupConsumer, Error> {
 		let state = self.state.borrow();
 
-		// TODO support more than just the latest group
-		if let Some(latest) = &state.latest {
-			if latest.sequence == sequence {
-				return Ok(latest.clone());
-			}
+		if let Some(group) = state.available(Instant::now()).find(|group| group.sequence == sequence) {
+			return Ok(group.clone());
 		}
 
 		state.closed.clone()?;
 		Err(Error::NotFound)
 	}
 
-	// NOTE: This can return groups out of order.
-	// TODO obey order
+	/// Returns any available groups within the range, oldest first, including the latest group.
+	///
+	/// [Self::next_group] will not return these groups again.
+	pub fn cached<R: RangeBounds<u64>>(&mut self, range: R) -> Vec<GroupConsumer> {
+		let state = self.state.borrow();
+		let now = Instant::now();
+
+		let groups: Vec<_> = state
+			.available(now)
+			.filter(|group| range.contains(&group.sequence))
+			.cloned()
+			.collect();
+
+		self.returned.extend(groups.iter().map(|group| group.sequence));
+
+		groups
+	}
+
+	/// Returns the next group in the track's [GroupOrder], or None if the track has ended.
+	///
+	/// A slow consumer still gets every group, provided it's retained long enough (see [Retention]).
+	/// Use [Self::next] to also find out about groups that were dropped.
 	pub async fn next_group(&mut self) -> Result<Option<GroupConsumer>, Error> {
-		// Wait until there's a new latest group or the track is closed.
+		loop {
+			match self.next().await? {
+				Some(TrackEvent::Group(group)) => return Ok(Some(group)),
+				Some(TrackEvent::Dropped(_)) => continue,
+				None => return Ok(None),
+			}
+		}
+	}
+
+	/// Returns the next group, or any groups the publisher dropped, or None if the track has ended.
+	///
+	/// Drops are returned as soon as they're reported, so a player can conceal the gap instead of waiting for a timeout.
+	pub async fn next(&mut self) -> Result<Option<TrackEvent>, Error> {
+		let order = self.info.order;
+		let start = &mut self.start;
+		let returned = &self.returned;
+		let dropped = &mut self.dropped;
+
+		// Wait until there's a drop or a group we haven't returned yet, or the track is closed.
 		let state = match self
 			.state
-			.wait_for(|state| state.latest.as_ref().map(|group| group.sequence) != self.prev || state.closed.is_err())
+			.wait_for(|state| {
+				Self::pending_drop(state, *start, dropped).is_some()
+					|| Self::pending(state, order, start, returned).is_some()
+					|| state.closed.is_err()
+			})
 			.await
 		{
 			Ok(state) => state,
 			Err(_) => return Ok(None),
 		};
 
-		// If there's a new latest group, return it.
-		if let Some(group) = state.latest.as_ref() {
-			if Some(group.sequence) != self.prev {
-				self.prev = Some(group.sequence);
-				return Ok(Some(group.clone()));
+		self.skipped += Self::count_skipped(&state, *start, returned, &mut self.evicted);
+
+		if let Some(gap) = Self::pending_drop(&state, *start, dropped) {
+			*dropped += 1;
+			return Ok(Some(TrackEvent::Dropped(gap)));
+		}
+
+		if let Some(group) = Self::pending(&state, order, start, returned).cloned() {
+			// Forget about groups that have been evicted, since they can't be returned anyway.
+			let oldest = state.oldest();
+			drop(state);
+
+			if let Some(oldest) = oldest {
+				self.returned = self.returned.split_off(&oldest);
 			}
+
+			self.returned.insert(group.sequence);
+			return Ok(Some(TrackEvent::Group(group)));
 		}
 
 		// Otherwise the track is closed.
 		Err(state.closed.clone().unwrap_err())
 	}
 
+	/// Returns how many groups were evicted or expired before they could be returned, since the last call.
+	///
+	/// Only groups after the starting point are counted, so this indicates the consumer is falling behind.
+	pub fn skipped(&mut self) -> u64 {
+		let state = self.state.borrow();
+		let skipped = self.skipped + Self::count_skipped(&state, self.start, &self.returned, &mut self.evicted);
+		self.skipped = 0;
+
+		skipped
+	}
+
+	// Returns how many newly evicted groups we skipped, updating how many evictions we've seen.
+	fn count_skipped(state: &TrackState, start: Option<u64>, returned: &BTreeSet<u64>, seen: &mut u64) -> u64 {
+		let unseen = state.evicted_total - *seen;
+		*seen = state.evicted_total;
+
+		// We haven't started reading, so we couldn't have skipped anything.
+		let start = match start {
+			Some(start) => start,
+			None => return 0,
+		};
+
+		// If we've fallen too far behind to remember, then assume we skipped the forgotten groups too.
+		let known = unseen.min(state.evicted.len() as u64);
+		let forgotten = unseen - known;
+
+		let skipped = state
+			.evicted
+			.iter()
+			.rev()
+			.take(known as usize)
+			.filter(|sequence| **sequence >= start && !returned.contains(sequence))
+			.count();
+
+		skipped as u64 + forgotten
+	}
+
+	/// The TTL configured by the producer, if any.
+	pub fn ttl(&self) -> Option<Duration> {
+		self.state.borrow().ttl
+	}
+
+	/// How the producer wants groups to be delivered.
+	pub fn delivery(&self) -> Delivery {
+		self.state.borrow().delivery
+	}
+
+	// Returns the next drop we haven't returned, skipping any before our starting point.
+	fn pending_drop(state: &TrackState, start: Option<u64>, seen: &mut u64) -> Option<Dropped> {
+		// If we've fallen too far behind, then we only return the drops we remember.
+		let first = state.dropped_total - state.dropped.len() as u64;
+		*seen = (*seen).max(first);
+
+		for gap in state.dropped.iter().skip((*seen - first) as usize) {
+			if start.is_none_or(|start| gap.sequence + gap.count > start) {
+				return Some(*gap);
+			}
+
+			*seen += 1;
+		}
+
+		None
+	}
+
+	// Returns the next group to return in the given order, if any.
+	fn pending<'a>(
+		state: &'a TrackState,
+		order: GroupOrder,
+		start: &mut Option<u64>,
+		returned: &BTreeSet<u64>,
+	) -> Option<&'a GroupConsumer> {
+		// Start at the latest group unless told otherwise.
+		let start = match start {
+			Some(start) => *start,
+			None => *start.insert(state.latest.as_ref()?.sequence),
+		};
+
+		let pending = state
+			.available(Instant::now())
+			.filter(|group| group.sequence >= start && !returned.contains(&group.sequence));
+
+		match order {
+			GroupOrder::Asc => pending.min_by_key(|group| group.sequence),
+			GroupOrder::Desc => pending.max_by_key(|group| group.sequence),
+		}
+	}
+
 	// Returns the largest group
 	pub fn latest_group(&self) -> u64 {
 		let state = self.state.borrow();
@@ -279,3 +681,390 @@ impl ops::Deref for TrackConsumer {
 		&self.info
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures::FutureExt;
+
+	fn sequences(groups: Vec<GroupConsumer>) -> Vec<u64> {
+		groups.iter().map(|group| group.sequence).collect()
+	}
+
+	#[test]
+	fn latest_only() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+
+		producer.append_group();
+		producer.append_group();
+
+		assert!(consumer.get_group(0).is_err());
+		assert!(consumer.get_group(1).is_ok());
+		assert_eq!(sequences(consumer.cached(..)), vec![1]);
+	}
+
+	#[test]
+	fn retention_groups() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+		producer.set_retention(Retention {
+			groups: 2,
+			..Default::default()
+		});
+
+		for _ in 0..5 {
+			producer.append_group();
+		}
+
+		assert!(consumer.get_group(1).is_err());
+		assert!(consumer.get_group(2).is_ok());
+		assert_eq!(sequences(consumer.cached(..)), vec![2, 3, 4]);
+		assert_eq!(sequences(consumer.cached(3..)), vec![3, 4]);
+		assert_eq!(sequences(consumer.cached(..=3)), vec![2, 3]);
+	}
+
+	#[test]
+	fn retention_bytes() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			bytes: Some(5),
+			..Default::default()
+		});
+
+		for _ in 0..4 {
+			producer.append_group().write_frame(bytes::Bytes::from_static(b"abc"));
+		}
+
+		// Only one older group fits in 5 bytes.
+		assert_eq!(sequences(consumer.cached(..)), vec![2, 3]);
+	}
+
+	#[test]
+	fn late_group() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			..Default::default()
+		});
+
+		producer.create_group(0);
+		producer.create_group(2);
+		producer.create_group(1);
+
+		assert_eq!(sequences(consumer.cached(..)), vec![0, 1, 2]);
+	}
+
+	#[test]
+	fn cached_skips_latest() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+
+		producer.append_group();
+		assert_eq!(sequences(consumer.cached(..)), vec![0]);
+
+		// The latest group was already returned.
+		assert!(consumer.next_group().now_or_never().is_none());
+
+		producer.append_group();
+		let group = consumer.next_group().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(group.sequence, 1);
+	}
+
+	fn next(consumer: &mut TrackConsumer) -> Option<u64> {
+		let group = consumer.next_group().now_or_never()?.unwrap()?;
+		Some(group.sequence)
+	}
+
+	#[test]
+	fn slow_consumer_asc() {
+		let (mut producer, mut consumer) = Track::build().path("test").group_order(GroupOrder::Asc).produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			..Default::default()
+		});
+
+		producer.append_group();
+		assert_eq!(next(&mut consumer), Some(0));
+
+		// The consumer falls behind, but still gets every group in order.
+		for _ in 0..3 {
+			producer.append_group();
+		}
+
+		assert_eq!(next(&mut consumer), Some(1));
+		assert_eq!(next(&mut consumer), Some(2));
+		assert_eq!(next(&mut consumer), Some(3));
+		assert_eq!(next(&mut consumer), None);
+	}
+
+	#[test]
+	fn slow_consumer_desc() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			..Default::default()
+		});
+
+		producer.append_group();
+		assert_eq!(next(&mut consumer), Some(0));
+
+		for _ in 0..3 {
+			producer.append_group();
+		}
+
+		// Newest first, then any older groups we haven't returned.
+		assert_eq!(next(&mut consumer), Some(3));
+		assert_eq!(next(&mut consumer), Some(2));
+		assert_eq!(next(&mut consumer), Some(1));
+		assert_eq!(next(&mut consumer), None);
+	}
+
+	#[test]
+	fn slow_consumer_evicted() {
+		let (mut producer, mut consumer) = Track::build().path("test").group_order(GroupOrder::Asc).produce();
+		producer.set_retention(Retention {
+			groups: 1,
+			..Default::default()
+		});
+
+		producer.append_group();
+		assert_eq!(next(&mut consumer), Some(0));
+
+		for _ in 0..4 {
+			producer.append_group();
+		}
+
+		// Groups 1 and 2 were evicted before we could read them.
+		assert_eq!(next(&mut consumer), Some(3));
+		assert_eq!(next(&mut consumer), Some(4));
+		assert_eq!(next(&mut consumer), None);
+	}
+
+	#[test]
+	fn start_at() {
+		let (mut producer, _) = Track::build().path("test").group_order(GroupOrder::Asc).produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			..Default::default()
+		});
+
+		for _ in 0..5 {
+			producer.append_group();
+		}
+
+		// By default, we start at the latest group.
+		let mut consumer = producer.subscribe();
+		assert_eq!(next(&mut consumer), Some(4));
+		assert_eq!(next(&mut consumer), None);
+
+		let mut consumer = producer.subscribe();
+		consumer.start_at(2);
+		assert_eq!(next(&mut consumer), Some(2));
+		assert_eq!(next(&mut consumer), Some(3));
+		assert_eq!(next(&mut consumer), Some(4));
+		assert_eq!(next(&mut consumer), None);
+
+		// A start ahead of the latest group waits for it, instead of returning the latest group.
+		let mut consumer = producer.subscribe();
+		consumer.start_at(6);
+		assert_eq!(next(&mut consumer), None);
+
+		producer.append_group();
+		assert_eq!(next(&mut consumer), None);
+
+		producer.append_group();
+		assert_eq!(next(&mut consumer), Some(6));
+	}
+
+	#[test]
+	fn late_group_returned() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			..Default::default()
+		});
+
+		producer.create_group(0);
+		assert_eq!(next(&mut consumer), Some(0));
+
+		producer.create_group(2);
+		assert_eq!(next(&mut consumer), Some(2));
+
+		// Group 1 arrived late, but it's still returned.
+		producer.create_group(1);
+		assert_eq!(next(&mut consumer), Some(1));
+		assert_eq!(next(&mut consumer), None);
+	}
+
+	#[test]
+	fn closed() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+
+		producer.append_group();
+		drop(producer);
+
+		// The latest group is still returned after the producer is gone.
+		assert_eq!(next(&mut consumer), Some(0));
+		assert!(consumer.next_group().now_or_never().unwrap().unwrap().is_none());
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn ttl() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			..Default::default()
+		});
+		producer.set_ttl(Some(Duration::from_millis(20)));
+
+		producer.append_group();
+
+		tokio::time::advance(Duration::from_millis(19)).await;
+		assert!(consumer.get_group(0).is_ok());
+
+		tokio::time::advance(Duration::from_millis(1)).await;
+
+		// The group is too old to be returned.
+		assert!(consumer.get_group(0).is_err());
+		assert!(consumer.cached(..).is_empty());
+		assert_eq!(next(&mut consumer), None);
+
+		producer.append_group();
+		assert_eq!(next(&mut consumer), Some(1));
+	}
+
+	#[test]
+	fn delivery() {
+		let (producer, consumer) = Track::new("test").produce();
+		assert_eq!(consumer.delivery(), Delivery::Stream);
+
+		// Existing consumers see the change, ex. a publisher serving them.
+		producer.set_delivery(Delivery::Datagram);
+		assert_eq!(consumer.delivery(), Delivery::Datagram);
+		assert_eq!(producer.subscribe().delivery(), Delivery::Datagram);
+	}
+
+	#[test]
+	fn skipped() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+
+		producer.append_group();
+		assert_eq!(next(&mut consumer), Some(0));
+		assert_eq!(consumer.skipped(), 0);
+
+		for _ in 0..3 {
+			producer.append_group();
+		}
+
+		// Only the latest group is retained, so we skipped the others.
+		assert_eq!(next(&mut consumer), Some(3));
+		assert_eq!(consumer.skipped(), 2);
+		assert_eq!(consumer.skipped(), 0);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn skipped_expired() {
+		let (mut producer, mut consumer) = Track::build().path("test").group_order(GroupOrder::Asc).produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			..Default::default()
+		});
+		producer.set_ttl(Some(Duration::from_millis(20)));
+
+		producer.append_group();
+		assert_eq!(next(&mut consumer), Some(0));
+
+		producer.append_group();
+		producer.append_group();
+		tokio::time::advance(Duration::from_millis(20)).await;
+		producer.append_group();
+
+		// Group 2 was the latest but it expired too.
+		assert_eq!(next(&mut consumer), Some(3));
+		assert_eq!(consumer.skipped(), 2);
+	}
+
+	#[test]
+	fn skipped_before_start() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+
+		// Nothing is skipped before we start reading.
+		for _ in 0..3 {
+			producer.append_group();
+		}
+
+		assert_eq!(next(&mut consumer), Some(2));
+		assert_eq!(consumer.skipped(), 0);
+	}
+
+	#[test]
+	fn dropped() {
+		let (mut producer, mut consumer) = Track::new("test").produce();
+
+		producer.append_group();
+		assert!(matches!(
+			consumer.next().now_or_never(),
+			Some(Ok(Some(TrackEvent::Group(_))))
+		));
+
+		let dropped = Dropped {
+			sequence: 1,
+			count: 2,
+			code: Error::Expired.to_code(),
+		};
+		producer.drop_groups(dropped);
+		producer.create_group(3);
+
+		// The drop is reported before the next group.
+		match consumer.next().now_or_never() {
+			Some(Ok(Some(TrackEvent::Dropped(event)))) => assert_eq!(event, dropped),
+			event => panic!("unexpected event: {:?}", event),
+		}
+
+		match consumer.next().now_or_never() {
+			Some(Ok(Some(TrackEvent::Group(group)))) => assert_eq!(group.sequence, 3),
+			event => panic!("unexpected event: {:?}", event),
+		}
+
+		assert!(consumer.next().now_or_never().is_none());
+
+		// An empty drop isn't reported.
+		producer.drop_groups(Dropped { count: 0, ..dropped });
+		assert!(consumer.next().now_or_never().is_none());
+	}
+
+	#[test]
+	fn dropped_before_start() {
+		let (mut producer, _) = Track::new("test").produce();
+		producer.set_retention(Retention {
+			groups: 10,
+			..Default::default()
+		});
+
+		for _ in 0..5 {
+			producer.append_group();
+		}
+
+		let mut consumer = producer.subscribe();
+		consumer.start_at(3);
+
+		// Only drops after our starting point are reported.
+		producer.drop_groups(Dropped {
+			sequence: 0,
+			count: 2,
+			code: 0,
+		});
+		producer.drop_groups(Dropped {
+			sequence: 2,
+			count: 2,
+			code: 0,
+		});
+
+		match consumer.next().now_or_never() {
+			Some(Ok(Some(TrackEvent::Dropped(event)))) => assert_eq!(event.sequence, 2),
+			event => panic!("unexpected event: {:?}", event),
+		}
+
+		// next_group ignores drops.
+		assert_eq!(next(&mut consumer), Some(4));
+	}
+}
@@ -82,8 +82,13 @@ impl SubscriberManager {
                             if Instant::now() > timeout {
                                 break;
                             }
+                        } else if let Some(stats) = line.strip_prefix("📶") {
+                            // After handshake, show the connection quality and keep draining output to prevent pipe blocking
+                            let _ = tx_clone.send(format!(
+                                "▶️ Connected to station {} at {} ({})",
+                                station_arg_clone, url_string, stats.trim()
+                            ));
                         }
-                        // After handshake, continue draining output to prevent pipe blocking
                     }
                 }
 
@@ -12,6 +12,9 @@ use clap::Parser;
 use tokio::fs::File;
 use url::Url;
 
+/// Audio older than this is useless to a live listener, so relays stop serving it.
+pub static AUDIO_TTL: Duration = Duration::from_secs(10);
+
 /// Command-line config for the MoQ audio application.
 #[derive(Parser, Clone)]
 pub struct Config {
@@ -181,6 +184,7 @@ pub async fn perform_pub_cmd(config: Config, mut session: Session) -> anyhow::Re
             // Create and announce new audio track
             let track = Track::new(track_name.clone());
             let (writer, reader) = track.produce();
+            writer.set_ttl(Some(AUDIO_TTL));
             session.publish(reader.clone()).context("failed to announce broadcast")?;
 
             // Abort previous metadata task (if any)
@@ -193,6 +197,12 @@ pub async fn perform_pub_cmd(config: Config, mut session: Session) -> anyhow::Re
             let (md_writer, md_reader) = metadata_track.produce();
             session.publish(md_reader.clone()).context("failed to announce metadata track")?;
 
+            // Announce the song title on its own track so gateways can show what's playing
+            let title_track = Track::new(format!("title-{}", track_name));
+            let (mut title_writer, title_reader) = title_track.produce();
+            session.publish(title_reader).context("failed to announce title track")?;
+            title_writer.create_group(0).write_frame(song.clone());
+
             // Spawn async task to stream elapsed time to metadata track
             metadata_handle = Some(tokio::spawn({
                 let mut md_writer = md_writer.clone();
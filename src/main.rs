@@ -1,4 +1,5 @@
 use std::io::Cursor;
+use std::time::Duration;
 use moq_native::quic;
 use clap::Parser;
 use moq_transfork::*;
@@ -154,6 +155,9 @@ pub async fn sub_play_opus(mut reader: TrackConsumer, mut metadata_reader: Track
     Ok(())
 }
 
+// how often a subscriber prints the connection statistics
+static STATS_INTERVAL: Duration = Duration::from_secs(2);
+
 /// Entry point for publishing and subscribing
 ///
 #[tokio::main]
@@ -165,7 +169,7 @@ async fn main() -> anyhow::Result<()> {
     let tls = config.tls.load()?;
     let quic = quic::Endpoint::new(quic::Config { bind: config.bind, tls })?;
     let session = quic.client.connect(config.url.clone()).await?;
-    let mut session = Session::connect(session).await?;
+    let mut session = quic::moq_connect(session).await?;
 
     match config.role {
         Cmd::Publish => {
@@ -177,6 +181,17 @@ async fn main() -> anyhow::Result<()> {
             let port = 3030 + config.station_index - 1;
             println!("Starting WebSocket server on port {}", port);
 
+            // Periodically report the connection quality, which the TUI shows next to the station
+            let stats_session = session.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(STATS_INTERVAL).await;
+                    if let Some(conn) = stats_session.stats().connection {
+                        println!("📶 rtt={}ms lost={}", conn.rtt.as_millis(), conn.lost_packets);
+                    }
+                }
+            });
+
             let mut song_index = 0;
             let mut loop_counter = 0;
             let playlist_len = 5;
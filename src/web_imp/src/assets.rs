@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use tokio::fs;
+
+/*
+A single web UI file baked into the binary at compile time
+ */
+struct Asset {
+    path: &'static str,
+    body: &'static [u8],
+    etag: OnceLock<String>,
+}
+
+impl Asset {
+    const fn new(path: &'static str, body: &'static [u8]) -> Self {
+        Self { path, body, etag: OnceLock::new() }
+    }
+
+    // hashing the logo on every request would be a waste, so do it once
+    fn etag(&self) -> &str {
+        self.etag.get_or_init(|| etag(self.body))
+    }
+}
+
+// everything the web UI needs, relative to the repo root
+static ASSETS: [Asset; 2] = [
+    Asset::new("index.html", include_bytes!("../../../index.html")),
+    Asset::new("bear_radio.png", include_bytes!("../../../bear_radio.png")),
+];
+
+/*
+Serves the web UI, either from the embedded bundle or from a directory on disk.
+The directory override is meant for development so edits show up without a rebuild.
+ */
+#[derive(Clone)]
+pub struct Assets {
+    dir: Option<PathBuf>,
+}
+
+impl Assets {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        if let Some(dir) = &dir {
+            println!("Serving web assets from {}", dir.display());
+        }
+
+        Self { dir }
+    }
+
+    pub async fn serve(&self, path: &str, headers: &HeaderMap) -> Response {
+        let path = match path.trim_start_matches('/') {
+            "" => "index.html",
+            path => path,
+        };
+
+        // don't let anyone wander outside of the assets directory
+        if path.split('/').any(|part| part == ".." || part.is_empty()) {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+
+        let (body, etag) = match &self.dir {
+            Some(dir) => match fs::read(dir.join(path)).await {
+                Ok(body) => {
+                    let etag = etag(&body);
+                    (Body::from(body), etag)
+                }
+                Err(_) => return StatusCode::NOT_FOUND.into_response(),
+            },
+            None => match ASSETS.iter().find(|asset| asset.path == path) {
+                Some(asset) => (Body::from(asset.body), asset.etag().to_string()),
+                None => return StatusCode::NOT_FOUND.into_response(),
+            },
+        };
+
+        let etag = HeaderValue::from_str(&etag).unwrap();
+
+        // the browser already has this exact file cached
+        if headers.get(header::IF_NONE_MATCH) == Some(&etag) {
+            return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+        }
+
+        let content_type = HeaderValue::from_static(content_type(Path::new(path)));
+
+        (
+            [
+                (header::CONTENT_TYPE, content_type),
+                (header::ETAG, etag),
+                (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+            ],
+            body,
+        )
+            .into_response()
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+/*
+Strong ETag from a 64-bit FNV-1a hash of the contents.
+Not cryptographic, it only has to change when the file does.
+ */
+fn etag(body: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in body {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("\"{:016x}\"", hash)
+}
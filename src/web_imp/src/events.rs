@@ -0,0 +1,176 @@
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::http::header;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use futures::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// how many events a slow browser can fall behind before it starts missing them
+static EVENT_CAPACITY: usize = 64;
+
+/*
+Station lifecycle and now-playing events, pushed to browsers over SSE.
+These travel separately from the binary audio frames on the websocket.
+ */
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StationEvent {
+    // the upstream track started delivering audio
+    Online { station: String },
+    // the upstream track stopped delivering audio
+    Offline { station: String },
+    // the connection to the relay was lost, retrying after the given delay
+    Reconnecting {
+        station: String,
+        attempt: u32,
+        retry_ms: u64,
+    },
+    // a new song started on the station
+    SongChanged {
+        station: String,
+        track: String,
+        title: Option<String>,
+    },
+    // number of browsers currently connected to the station
+    Listeners { station: String, count: usize },
+}
+
+// the latest known state, replayed to browsers when they first connect
+#[derive(Default)]
+struct Snapshot {
+    online: Option<StationEvent>,
+    song: Option<StationEvent>,
+    listeners: Option<StationEvent>,
+}
+
+/*
+Fans station events out to every connected browser.
+Cloning is cheap, all clones share the same channel and snapshot.
+ */
+#[derive(Clone)]
+pub struct StationEvents {
+    station: String,
+    tx: broadcast::Sender<StationEvent>,
+    snapshot: Arc<Mutex<Snapshot>>,
+}
+
+impl StationEvents {
+    pub fn new(station: String) -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            station,
+            tx,
+            snapshot: Default::default(),
+        }
+    }
+
+    pub fn online(&self) {
+        self.send(StationEvent::Online { station: self.station.clone() });
+    }
+
+    pub fn offline(&self) {
+        self.send(StationEvent::Offline { station: self.station.clone() });
+    }
+
+    pub fn reconnecting(&self, attempt: u32, retry: Duration) {
+        self.send(StationEvent::Reconnecting {
+            station: self.station.clone(),
+            attempt,
+            retry_ms: retry.as_millis() as u64,
+        });
+    }
+
+    pub fn song_changed(&self, track: String, title: Option<String>) {
+        self.send(StationEvent::SongChanged {
+            station: self.station.clone(),
+            track,
+            title,
+        });
+    }
+
+    pub fn listeners(&self, count: usize) {
+        self.send(StationEvent::Listeners {
+            station: self.station.clone(),
+            count,
+        });
+    }
+
+    // title of the song currently playing, if the publisher told us
+    pub fn title(&self) -> Option<String> {
+        match &self.snapshot.lock().unwrap().song {
+            Some(StationEvent::SongChanged { title, .. }) => title.clone(),
+            _ => None,
+        }
+    }
+
+    fn send(&self, event: StationEvent) {
+        {
+            let mut snapshot = self.snapshot.lock().unwrap();
+            match &event {
+                StationEvent::Online { .. } | StationEvent::Offline { .. } => {
+                    // only announce actual transitions, not every retry
+                    if snapshot.online.as_ref().map(std::mem::discriminant) == Some(std::mem::discriminant(&event)) {
+                        return;
+                    }
+                    snapshot.online = Some(event.clone());
+                }
+                // every attempt is news, but new browsers only need the latest one
+                StationEvent::Reconnecting { .. } => snapshot.online = Some(event.clone()),
+                StationEvent::SongChanged { .. } => snapshot.song = Some(event.clone()),
+                StationEvent::Listeners { .. } => snapshot.listeners = Some(event.clone()),
+            }
+        }
+
+        // no receivers just means nobody is listening right now
+        let _ = self.tx.send(event);
+    }
+
+    /*
+    Subscribe to all future events, starting with a replay of the current state
+     */
+    pub fn subscribe(&self) -> impl Stream<Item = StationEvent> {
+        let rx = self.tx.subscribe();
+
+        let initial: Vec<StationEvent> = {
+            let snapshot = self.snapshot.lock().unwrap();
+            [&snapshot.online, &snapshot.song, &snapshot.listeners]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect()
+        };
+
+        let live = stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => return Some((event, rx)),
+                    // a slow client missed some events, just keep going with the newest
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        stream::iter(initial).chain(live)
+    }
+}
+
+/*
+SSE handler, each event is sent as a JSON object with a "type" field.
+Every station runs on its own port, so allow the page from any of them to listen.
+ */
+pub async fn sse_handler(events: StationEvents) -> impl IntoResponse {
+    let stream = events.subscribe().map(|event| {
+        let data = serde_json::to_string(&event).unwrap();
+        Ok::<_, Infallible>(Event::default().data(data))
+    });
+
+    (
+        [(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")],
+        Sse::new(stream).keep_alive(KeepAlive::default()),
+    )
+}
@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::extract::Path;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use moq_karp::cmaf::{Fragmenter, Sample};
+use tokio::sync::broadcast;
+
+use crate::icecast::{opus_packet_samples, OpusTx};
+
+// the publisher always encodes 48kHz stereo
+static SAMPLE_RATE: u32 = 48000;
+static CHANNELS: u8 = 2;
+
+// a finished fMP4 media segment (moof + mdat)
+struct Segment {
+    sequence: u64,
+    // in Fragmenter::TIMESCALE units
+    duration: u64,
+    data: Bytes,
+    // packets are missing before this segment, so players need to resync
+    discontinuity: bool,
+}
+
+// everything a player can currently fetch
+struct Window {
+    segments: VecDeque<Segment>,
+    // segments that just left the playlist, kept until players that are mid-fetch are done
+    evicted: VecDeque<(Segment, Instant)>,
+    // how many discontinuities have left the playlist
+    discontinuity_sequence: u64,
+}
+
+// the fMP4 timeline, cut into segments as packets arrive
+struct Packager {
+    fragmenter: Fragmenter,
+    pending: Vec<Sample>,
+    pending_duration: u64,
+    decode_time: u64,
+    sequence: u64,
+    discontinuity: bool,
+}
+
+impl Packager {
+    /*
+    Segments are numbered from the wall clock, so a restarted gateway never reuses
+    the URL of a segment from the previous run that a CDN may still have cached.
+     */
+    fn new(segment_ms: u64) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        Self {
+            fragmenter: Fragmenter::opus(CHANNELS, SAMPLE_RATE),
+            pending: Vec::new(),
+            pending_duration: 0,
+            decode_time: 0,
+            sequence: now.as_millis() as u64 / segment_ms.max(1),
+            discontinuity: false,
+        }
+    }
+
+    // turns the pending packets into a segment, if there are any
+    fn cut(&mut self) -> Option<Segment> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let segment = Segment {
+            sequence: self.sequence,
+            duration: self.pending_duration,
+            data: self.fragmenter.fragment(self.decode_time, &self.pending),
+            discontinuity: self.discontinuity,
+        };
+
+        self.decode_time += self.pending_duration;
+        self.sequence += 1;
+        self.pending.clear();
+        self.pending_duration = 0;
+        self.discontinuity = false;
+
+        Some(segment)
+    }
+
+    /*
+    Packets went missing, so finish the current segment with what we have
+    and flag the next one. Nothing to flag before the first segment.
+     */
+    fn gap(&mut self) -> Option<Segment> {
+        let segment = self.cut();
+        self.discontinuity = self.decode_time > 0;
+        segment
+    }
+}
+
+/*
+Packages a station's Opus packets into fMP4 segments and a live HLS playlist.
+Only the last few segments are kept around, so the playlist is a rolling window
+and everything it points at can be cached by a plain HTTP CDN.
+ */
+#[derive(Clone)]
+pub struct Hls {
+    init: Bytes,
+    segment_ms: u64,
+    window_size: usize,
+    window: Arc<Mutex<Window>>,
+    // set when the upstream connection was re-established
+    reconnected: Arc<AtomicBool>,
+}
+
+impl Hls {
+    pub fn new(segment_ms: u64, window_size: usize) -> Self {
+        let init = Fragmenter::opus(CHANNELS, SAMPLE_RATE).init();
+
+        Self {
+            init,
+            segment_ms,
+            window_size: window_size.max(1),
+            window: Arc::new(Mutex::new(Window {
+                segments: VecDeque::new(),
+                evicted: VecDeque::new(),
+                discontinuity_sequence: 0,
+            })),
+            reconnected: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // the relay connection dropped and came back, so packets were lost in between
+    pub fn reconnected(&self) {
+        self.reconnected.store(true, Ordering::Relaxed);
+    }
+
+    /*
+    Cuts the raw Opus packets into segments of roughly segment_ms each.
+    Every Opus packet can be decoded on its own, so any packet boundary is a valid cut point.
+     */
+    pub async fn run(self, opus_tx: OpusTx) {
+        let mut rx = opus_tx.subscribe();
+        let mut packager = Packager::new(self.segment_ms);
+
+        let target = self.segment_ms * Fragmenter::TIMESCALE as u64 / 1000;
+
+        loop {
+            let packet = match rx.recv().await {
+                Ok(packet) => packet,
+                // the packager fell behind, the timeline just keeps going from the newest packet
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    println!("HLS packager lagged, skipped {} packets", n);
+                    if let Some(segment) = packager.gap() {
+                        self.push(segment);
+                    }
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+
+            // anything pending arrived before the outage
+            if self.reconnected.swap(false, Ordering::Relaxed) {
+                if let Some(segment) = packager.gap() {
+                    self.push(segment);
+                }
+            }
+
+            let duration = opus_packet_samples(&packet);
+            if duration == 0 {
+                continue;
+            }
+
+            packager.pending.push(Sample { duration, payload: packet });
+            packager.pending_duration += duration as u64;
+
+            if packager.pending_duration < target {
+                continue;
+            }
+
+            if let Some(segment) = packager.cut() {
+                self.push(segment);
+            }
+        }
+    }
+
+    fn push(&self, segment: Segment) {
+        let mut window = self.window.lock().unwrap();
+        window.segments.push_back(segment);
+
+        while window.segments.len() > self.window_size {
+            let evicted = window.segments.pop_front().unwrap();
+            if evicted.discontinuity {
+                window.discontinuity_sequence += 1;
+            }
+            window.evicted.push_back((evicted, Instant::now()));
+        }
+
+        // players may still be fetching a segment they saw in the last playlist
+        let grace = Duration::from_secs(target_duration(&window.segments));
+        while window.evicted.front().is_some_and(|(_, at)| at.elapsed() >= grace) {
+            window.evicted.pop_front();
+        }
+    }
+
+    // the live media playlist, or None until the first segment is ready
+    fn playlist(&self) -> Option<String> {
+        let window = self.window.lock().unwrap();
+        let first = window.segments.front()?;
+
+        let timescale = Fragmenter::TIMESCALE as f64;
+        let target_duration = target_duration(&window.segments);
+
+        let mut playlist = String::new();
+        playlist.push_str("#EXTM3U\n");
+        playlist.push_str("#EXT-X-VERSION:7\n");
+        playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", target_duration));
+        playlist.push_str(&format!("#EXT-X-MEDIA-SEQUENCE:{}\n", first.sequence));
+        playlist.push_str(&format!("#EXT-X-DISCONTINUITY-SEQUENCE:{}\n", window.discontinuity_sequence));
+        playlist.push_str("#EXT-X-INDEPENDENT-SEGMENTS\n");
+        playlist.push_str("#EXT-X-MAP:URI=\"init.mp4\"\n");
+
+        for segment in &window.segments {
+            if segment.discontinuity {
+                playlist.push_str("#EXT-X-DISCONTINUITY\n");
+            }
+            playlist.push_str(&format!("#EXTINF:{:.3},\n", segment.duration as f64 / timescale));
+            playlist.push_str(&format!("{}.m4s\n", segment.sequence));
+        }
+
+        Some(playlist)
+    }
+
+    fn segment(&self, sequence: u64) -> Option<Bytes> {
+        let window = self.window.lock().unwrap();
+        let grace = Duration::from_secs(target_duration(&window.segments));
+
+        let evicted = window
+            .evicted
+            .iter()
+            .filter(|(_, at)| at.elapsed() < grace)
+            .map(|(segment, _)| segment);
+
+        window
+            .segments
+            .iter()
+            .chain(evicted)
+            .find(|segment| segment.sequence == sequence)
+            .map(|segment| segment.data.clone())
+    }
+}
+
+// the longest segment rounded up to whole seconds, as EXT-X-TARGETDURATION wants
+fn target_duration<'a>(segments: impl IntoIterator<Item = &'a Segment>) -> u64 {
+    let timescale = Fragmenter::TIMESCALE as f64;
+
+    segments
+        .into_iter()
+        .map(|segment| (segment.duration as f64 / timescale).ceil() as u64)
+        .max()
+        .unwrap_or(1)
+}
+
+/*
+Serves index.m3u8, init.mp4 and the numbered .m4s segments for a station.
+The playlist changes every segment so it's never cached, but segments never change once written.
+ */
+pub async fn hls_handler(Path(file): Path<String>, hls: Hls) -> Response {
+    let mut response = match file.as_str() {
+        "index.m3u8" => match hls.playlist() {
+            Some(playlist) => (
+                [
+                    (header::CONTENT_TYPE, HeaderValue::from_static("application/vnd.apple.mpegurl")),
+                    (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+                ],
+                playlist,
+            )
+                .into_response(),
+            // players retry the playlist, so this just means "not yet"
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        "init.mp4" => (
+            [
+                (header::CONTENT_TYPE, HeaderValue::from_static("audio/mp4")),
+                (header::CACHE_CONTROL, HeaderValue::from_static("public, max-age=3600")),
+            ],
+            hls.init.clone(),
+        )
+            .into_response(),
+        file => {
+            let segment = file
+                .strip_suffix(".m4s")
+                .and_then(|sequence| sequence.parse().ok())
+                .and_then(|sequence| hls.segment(sequence));
+
+            match segment {
+                Some(data) => (
+                    [
+                        (header::CONTENT_TYPE, HeaderValue::from_static("audio/mp4")),
+                        (header::CACHE_CONTROL, HeaderValue::from_static("public, max-age=60")),
+                    ],
+                    data,
+                )
+                    .into_response(),
+                None => StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+    };
+
+    // hls.js players on other origins need this
+    response
+        .headers_mut()
+        .insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    response
+}
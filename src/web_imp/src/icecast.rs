@@ -0,0 +1,418 @@
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::stream;
+use tokio::sync::broadcast;
+
+use crate::events::StationEvents;
+
+// raw Opus packets straight from the MoQ track, before they get decoded for the websocket
+pub type OpusTx = broadcast::Sender<Bytes>;
+
+// bytes of audio between ICY metadata blocks, same default as Icecast
+static ICY_METAINT: usize = 16000;
+
+// the publisher always encodes 48kHz stereo
+static SAMPLE_RATE: u32 = 48000;
+static CHANNELS: u8 = 2;
+
+/*
+Serves a station as a never-ending Ogg/Opus file over plain HTTP, like an Icecast mount.
+Anything that can play an internet radio URL (VLC, car radios, smart speakers) can tune in.
+If the player asks for it with `Icy-MetaData: 1`, song titles are interleaved as ICY metadata.
+ */
+pub async fn stream_handler(headers: HeaderMap, opus_tx: OpusTx, events: StationEvents, station: String) -> Response {
+    let icy = headers
+        .get("icy-metadata")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim() == "1")
+        .unwrap_or(false);
+
+    println!("HTTP listener connected to {} (icy metadata: {})", station, icy);
+
+    let state = StreamState {
+        rx: opus_tx.subscribe(),
+        ogg: OggWriter::new(rand_serial()),
+        icy: icy.then(IcyWriter::new),
+        events,
+        headers_sent: false,
+    };
+
+    let body = stream::unfold(state, |mut state| async move {
+        let chunk = state.next().await?;
+        Some((Ok::<_, std::io::Error>(chunk), state))
+    });
+
+    let mut response = Body::from_stream(body).into_response();
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("audio/ogg"));
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache, no-store"));
+    response_headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    response_headers.insert("icy-name", HeaderValue::from_str(&station).unwrap_or(HeaderValue::from_static("Bear Radio")));
+    response_headers.insert("icy-description", HeaderValue::from_static("Bear Radio live stream"));
+    if icy {
+        response_headers.insert("icy-metaint", HeaderValue::from(ICY_METAINT));
+    }
+
+    response
+}
+
+struct StreamState {
+    rx: broadcast::Receiver<Bytes>,
+    ogg: OggWriter,
+    icy: Option<IcyWriter>,
+    events: StationEvents,
+    headers_sent: bool,
+}
+
+impl StreamState {
+    // returns the next chunk of the HTTP body, or None when the station shuts down
+    async fn next(&mut self) -> Option<Bytes> {
+        let mut ogg = BytesMut::new();
+
+        if !self.headers_sent {
+            self.ogg.write_headers(&mut ogg);
+            self.headers_sent = true;
+        }
+
+        loop {
+            match self.rx.recv().await {
+                Ok(packet) => {
+                    self.ogg.write_packet(&packet, &mut ogg);
+                    break;
+                }
+                // this listener fell behind, skip ahead instead of buffering forever
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    println!("HTTP listener lagged, skipped {} packets", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+
+        match self.icy.as_mut() {
+            Some(icy) => {
+                let title = self.events.title();
+                Some(icy.write(&ogg, title.as_deref()))
+            }
+            None => Some(ogg.freeze()),
+        }
+    }
+}
+
+/*
+Minimal Ogg muxer for a single Opus stream (RFC 3533 + RFC 7845).
+Every Opus packet gets its own page, which keeps latency at one packet (20ms).
+ */
+struct OggWriter {
+    serial: u32,
+    sequence: u32,
+    granule: u64,
+}
+
+impl OggWriter {
+    fn new(serial: u32) -> Self {
+        Self { serial, sequence: 0, granule: 0 }
+    }
+
+    // OpusHead and OpusTags, each on its own page as the spec requires
+    fn write_headers(&mut self, out: &mut BytesMut) {
+        let mut head = BytesMut::with_capacity(19);
+        head.put_slice(b"OpusHead");
+        head.put_u8(1); // version
+        head.put_u8(CHANNELS);
+        head.put_u16_le(0); // pre-skip, we're joining mid-stream anyway
+        head.put_u32_le(SAMPLE_RATE);
+        head.put_i16_le(0); // output gain
+        head.put_u8(0); // channel mapping family, mono/stereo
+        self.write_page(&head, 0x02, 0, out);
+
+        let vendor = b"bear-radio";
+        let mut tags = BytesMut::new();
+        tags.put_slice(b"OpusTags");
+        tags.put_u32_le(vendor.len() as u32);
+        tags.put_slice(vendor);
+        tags.put_u32_le(0); // no user comments, titles go over ICY
+        self.write_page(&tags, 0x00, 0, out);
+    }
+
+    fn write_packet(&mut self, packet: &[u8], out: &mut BytesMut) {
+        self.granule += opus_packet_samples(packet) as u64;
+        self.write_page(packet, 0x00, self.granule, out);
+    }
+
+    fn write_page(&mut self, packet: &[u8], header_type: u8, granule: u64, out: &mut BytesMut) {
+        // lacing values: a run of 255s followed by the remainder (which may be 0)
+        let mut segments = vec![255u8; packet.len() / 255];
+        segments.push((packet.len() % 255) as u8);
+
+        let start = out.len();
+        out.put_slice(b"OggS");
+        out.put_u8(0); // version
+        out.put_u8(header_type);
+        out.put_u64_le(granule);
+        out.put_u32_le(self.serial);
+        out.put_u32_le(self.sequence);
+        out.put_u32_le(0); // checksum, filled in below
+        out.put_u8(segments.len() as u8);
+        out.put_slice(&segments);
+        out.put_slice(packet);
+
+        let crc = ogg_crc(&out[start..]);
+        out[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+
+        self.sequence += 1;
+    }
+}
+
+/*
+Number of samples (per channel, at 48kHz) in an Opus packet, from its TOC byte (RFC 6716 3.1)
+ */
+pub(crate) fn opus_packet_samples(packet: &[u8]) -> u32 {
+    let toc = match packet.first() {
+        Some(toc) => *toc,
+        None => return 0,
+    };
+
+    let config = toc >> 3;
+    let frame_samples = match config {
+        // SILK: 10, 20, 40, 60 ms
+        0..=11 => [480, 960, 1920, 2880][(config % 4) as usize],
+        // Hybrid: 10, 20 ms
+        12..=15 => [480, 960][(config % 2) as usize],
+        // CELT: 2.5, 5, 10, 20 ms
+        _ => [120, 240, 480, 960][(config % 4) as usize],
+    };
+
+    let frames = match toc & 0x3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => packet.get(1).map(|b| (b & 0x3f) as u32).unwrap_or(0),
+    };
+
+    frame_samples * frames
+}
+
+// CRC-32 as used by Ogg: polynomial 0x04c11db7, no reflection, zero init
+fn ogg_crc(data: &[u8]) -> u32 {
+    static TABLE: [u32; 256] = ogg_crc_table();
+
+    data.iter().fold(0u32, |crc, byte| {
+        (crc << 8) ^ TABLE[(((crc >> 24) as u8) ^ byte) as usize]
+    })
+}
+
+const fn ogg_crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut r = (i as u32) << 24;
+        let mut j = 0;
+        while j < 8 {
+            r = if r & 0x80000000 != 0 { (r << 1) ^ 0x04c11db7 } else { r << 1 };
+            j += 1;
+        }
+        table[i] = r;
+        i += 1;
+    }
+    table
+}
+
+/*
+Interleaves ICY metadata blocks into the audio bytes every ICY_METAINT bytes.
+The title is only sent when it changes, otherwise an empty block (a single 0 byte) is written.
+ */
+struct IcyWriter {
+    // audio bytes left before the next metadata block
+    remaining: usize,
+    last_title: Option<String>,
+}
+
+impl IcyWriter {
+    fn new() -> Self {
+        Self {
+            remaining: ICY_METAINT,
+            last_title: None,
+        }
+    }
+
+    fn write(&mut self, mut audio: &[u8], title: Option<&str>) -> Bytes {
+        let mut out = BytesMut::with_capacity(audio.len() + 64);
+
+        while audio.len() >= self.remaining {
+            let (now, rest) = audio.split_at(self.remaining);
+            out.put_slice(now);
+            audio = rest;
+
+            self.write_metadata(title, &mut out);
+            self.remaining = ICY_METAINT;
+        }
+
+        out.put_slice(audio);
+        self.remaining -= audio.len();
+
+        out.freeze()
+    }
+
+    fn write_metadata(&mut self, title: Option<&str>, out: &mut BytesMut) {
+        if title.is_none() || title == self.last_title.as_deref() {
+            out.put_u8(0);
+            return;
+        }
+
+        let title = title.unwrap();
+        self.last_title = Some(title.to_string());
+
+        // single quotes would end the value early
+        let meta = format!("StreamTitle='{}';", title.replace('\'', "’"));
+
+        // length is in 16 byte blocks, padded with zeros, max 255 blocks
+        let mut meta = meta.into_bytes();
+        meta.truncate(255 * 16);
+        let blocks = meta.len().div_ceil(16);
+        meta.resize(blocks * 16, 0);
+
+        out.put_u8(blocks as u8);
+        out.put_slice(&meta);
+    }
+}
+
+// each listener gets its own logical Ogg stream, the serial just needs to be unlikely to repeat
+fn rand_serial() -> u32 {
+    uuid::Uuid::new_v4().as_u128() as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // splits ICY output back into the audio and each metadata block
+    fn parse_icy(mut data: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let mut audio = Vec::new();
+        let mut blocks = Vec::new();
+
+        while data.len() > ICY_METAINT {
+            audio.extend_from_slice(&data[..ICY_METAINT]);
+            let len = data[ICY_METAINT] as usize * 16;
+            blocks.push(data[ICY_METAINT + 1..ICY_METAINT + 1 + len].to_vec());
+            data = &data[ICY_METAINT + 1 + len..];
+        }
+
+        audio.extend_from_slice(data);
+        (audio, blocks)
+    }
+
+    #[test]
+    fn crc_check_value() {
+        // the standard check input for this CRC-32 variant
+        assert_eq!(ogg_crc(b"123456789"), 0x89a1897f);
+        assert_eq!(ogg_crc(b""), 0);
+    }
+
+    #[test]
+    fn crc_page() {
+        let mut writer = OggWriter::new(0x12345678);
+        let mut out = BytesMut::new();
+        writer.write_headers(&mut out);
+
+        // the OpusHead page: 27 byte header, one lacing value, 19 byte packet
+        let page = &out[..27 + 1 + 19];
+        assert_eq!(&page[..4], b"OggS");
+        assert_eq!(page[5], 0x02);
+        assert_eq!(&page[14..18], &0x12345678u32.to_le_bytes());
+        assert_eq!(page[26], 1);
+        assert_eq!(page[27], 19);
+        assert_eq!(&page[22..26], &0xc39c1fbbu32.to_le_bytes());
+
+        // a reader verifies the page by zeroing the checksum and recomputing it
+        let mut zeroed = page.to_vec();
+        zeroed[22..26].fill(0);
+        assert_eq!(ogg_crc(&zeroed), 0xc39c1fbb);
+
+        // the OpusTags page follows with the next page sequence
+        let tags = &out[page.len()..];
+        assert_eq!(&tags[..4], b"OggS");
+        assert_eq!(&tags[18..22], &1u32.to_le_bytes());
+    }
+
+    #[test]
+    fn icy_placement() {
+        let mut icy = IcyWriter::new();
+
+        let input: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let mut output = Vec::new();
+
+        // chunks that end before, exactly on and across the metadata boundary
+        let mut rest = &input[..];
+        for size in [1, 15998, 1, 16000, 7000, 9000, 40000].into_iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+
+            let (chunk, remain) = rest.split_at(size.min(rest.len()));
+            output.extend_from_slice(&icy.write(chunk, Some("Song")));
+            rest = remain;
+        }
+
+        let (audio, blocks) = parse_icy(&output);
+        assert_eq!(audio, input);
+        assert_eq!(blocks.len(), input.len() / ICY_METAINT);
+
+        // the title is only repeated when it changes
+        assert_eq!(blocks[0], b"StreamTitle='Song';\0\0\0\0\0\0\0\0\0\0\0\0\0");
+        assert!(blocks[1..].iter().all(|block| block.is_empty()));
+    }
+
+    #[test]
+    fn icy_title() {
+        let mut icy = IcyWriter::new();
+        let audio = vec![0u8; ICY_METAINT];
+
+        // no title yet, so an empty block
+        let (_, blocks) = parse_icy(&icy.write(&audio, None));
+        assert_eq!(blocks, vec![Vec::<u8>::new()]);
+
+        // padded with zeros to the next 16 byte block
+        let (_, blocks) = parse_icy(&icy.write(&audio, Some("abc")));
+        assert_eq!(blocks, vec![[&b"StreamTitle='abc';"[..], &[0; 14]].concat()]);
+
+        // exactly 16 bytes doesn't need any padding
+        let title = "x".repeat(16 - "StreamTitle='';".len());
+        let (_, blocks) = parse_icy(&icy.write(&audio, Some(&title)));
+        assert_eq!(blocks[0].len(), 16);
+        assert_eq!(blocks[0], format!("StreamTitle='{}';", title).into_bytes());
+
+        // quotes are replaced so they don't end the value early
+        let (_, blocks) = parse_icy(&icy.write(&audio, Some("Don't Stop")));
+        assert!(blocks[0].starts_with("StreamTitle='Don’t Stop';".as_bytes()));
+
+        // long titles are cut off at the largest block ICY allows
+        let long = "y".repeat(5000);
+        let (_, blocks) = parse_icy(&icy.write(&audio, Some(&long)));
+        assert_eq!(blocks[0].len(), 255 * 16);
+        assert!(blocks[0].starts_with(b"StreamTitle='yyy"));
+        assert!(blocks[0].iter().all(|&b| b != 0));
+    }
+
+    #[test]
+    fn samples() {
+        // SILK, 20ms and 60ms frames
+        assert_eq!(opus_packet_samples(&[1 << 3]), 960);
+        assert_eq!(opus_packet_samples(&[(3 << 3) | 1]), 2 * 2880);
+        assert_eq!(opus_packet_samples(&[(11 << 3) | 2]), 2 * 2880);
+
+        // Hybrid, 10ms and 20ms frames
+        assert_eq!(opus_packet_samples(&[12 << 3]), 480);
+        assert_eq!(opus_packet_samples(&[(15 << 3) | 1]), 2 * 960);
+
+        // CELT, code 3 packets carry the frame count (and VBR/padding flags) in the second byte
+        assert_eq!(opus_packet_samples(&[(31 << 3) | 3, 0x83]), 3 * 960);
+        assert_eq!(opus_packet_samples(&[(16 << 3) | 3, 48]), 48 * 120);
+        assert_eq!(opus_packet_samples(&[(29 << 3) | 3, 0x40 | 4]), 4 * 240);
+
+        // malformed packets don't advance the granule position
+        assert_eq!(opus_packet_samples(&[]), 0);
+        assert_eq!(opus_packet_samples(&[(31 << 3) | 3]), 0);
+    }
+}
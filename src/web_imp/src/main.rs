@@ -1,11 +1,22 @@
+mod assets;
+mod events;
+mod hls;
+mod icecast;
 mod web_server;
 
+use assets::Assets;
+use events::StationEvents;
+use hls::Hls;
+use icecast::OpusTx;
 use web_server::{run_ws_server, AudioTx};
 
+use std::convert::Infallible;
 use std::io::Cursor;
 use moq_native::quic;
+use tokio::sync::watch;
 use tokio::time::{sleep, timeout, Duration};
 use std::net;
+use std::path::PathBuf;
 
 use url::Url;
 
@@ -21,6 +32,7 @@ use std::process::Command;
 use opus::Decoder as OpusDecoder;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use final_project_group3_s25::AUDIO_TTL;
 use rodio::cpal::FromSample;
 use tokio::fs::File;
 
@@ -52,6 +64,18 @@ pub struct Config {
 
     #[arg(long)]
     pub station_index: u16,
+
+    /// Serve the web UI from this directory instead of the copy built into the binary.
+    #[arg(long)]
+    pub assets_dir: Option<PathBuf>,
+
+    /// Target length of each HLS segment, in milliseconds.
+    #[arg(long, default_value = "2000")]
+    pub hls_segment_ms: u64,
+
+    /// Number of segments kept in the live HLS playlist.
+    #[arg(long, default_value = "6")]
+    pub hls_window: usize,
 }
 
 // Possible CMD Line arguments
@@ -136,17 +160,38 @@ pub async fn pub_opus_from_mp3(path: &str, mut writer: TrackProducer) -> anyhow:
     Ok(())
 }
 
-pub async fn sub_play_opus(mut reader: TrackConsumer, tx: AudioTx) -> anyhow::Result<()> {
+/*
+Reads the song title the publisher puts on the matching title track.
+Older publishers don't have one, so give up quickly and return None.
+ */
+pub async fn read_title(mut reader: TrackConsumer) -> Option<String> {
+    let read = async {
+        let mut group = reader.next_group().await.ok()??;
+        let frame = group.read_frame().await.ok()??;
+        String::from_utf8(frame.to_vec()).ok()
+    };
+
+    timeout(Duration::from_secs(1), read).await.ok().flatten()
+}
+
+pub async fn sub_play_opus(mut reader: TrackConsumer, tx: AudioTx, opus_tx: OpusTx, events: StationEvents, title: Option<String>) -> anyhow::Result<()> {
     let sample_rate = 48000;
     let channels = Channels::Stereo;
     let mut decoder = OpusDecoder::new(sample_rate, channels)?;
     let mut pcm_buf = [0i16; 960 * 2];
+    let mut title = Some(title);
 
 
     loop {
         match timeout(Duration::from_secs(5), reader.next_group()).await {
             Ok(Ok(Some(mut group))) => {
                 println!("Received new group! Starting to process frames...");
+                events.online();
+
+                // only report the song once audio is actually flowing
+                if let Some(title) = title.take() {
+                    events.song_changed(reader.path.clone(), title);
+                }
 
                 while let Some(mut frame) = group.next_frame().await? {
                     // 1. Collect the frame
@@ -176,6 +221,9 @@ pub async fn sub_play_opus(mut reader: TrackConsumer, tx: AudioTx) -> anyhow::Re
 
                     let packet = &full[4..4 + packet_len];
 
+                    // HTTP listeners get the Opus packet as-is, no need to decode
+                    let _ = opus_tx.send(Bytes::copy_from_slice(packet));
+
                     // 4. Decode Opus packet into PCM samples
                     let samples = decoder.decode(packet, &mut pcm_buf, false)?;
                     let pcm_bytes = bytemuck::cast_slice(&pcm_buf[..samples * 2]).to_vec();
@@ -190,10 +238,12 @@ pub async fn sub_play_opus(mut reader: TrackConsumer, tx: AudioTx) -> anyhow::Re
             }
             Ok(Err(e)) => {
                 eprintln!("Error while getting next group: {:?}", e);
+                events.offline();
                 break;
             }
             Err(_) => {
                 println!("Timeout while waiting for new group. Assuming track is unavailable.");
+                events.offline();
                 break;
             }
         }
@@ -203,6 +253,133 @@ pub async fn sub_play_opus(mut reader: TrackConsumer, tx: AudioTx) -> anyhow::Re
     Ok(())
 }
 
+// how long to wait between reconnect attempts, doubling after every failure
+static RECONNECT_MIN: Duration = Duration::from_secs(1);
+static RECONNECT_MAX: Duration = Duration::from_secs(30);
+
+// how long browsers get to disconnect on shutdown
+static DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the publisher cycles through this many songs per loop
+static PLAYLIST_LEN: usize = 5;
+
+pub async fn connect(client: &quic::Client, url: &Url) -> anyhow::Result<Session> {
+    let session = client.connect(url.clone()).await?;
+    let session = quic::moq_connect(session).await?;
+    Ok(session)
+}
+
+/*
+Keeps trying to connect to the relay with exponential backoff.
+Browsers are told about every attempt so they can show the outage.
+ */
+pub async fn reconnect(client: &quic::Client, url: &Url, events: &StationEvents) -> Session {
+    let mut delay = RECONNECT_MIN;
+    let mut attempt = 1;
+
+    loop {
+        match connect(client, url).await {
+            Ok(session) => {
+                println!("Connected to relay at {}", url);
+                return session;
+            }
+            Err(e) => {
+                eprintln!("Failed to connect to relay (attempt {}): {:?}. Retrying in {:?}", attempt, e, delay);
+                events.reconnecting(attempt, delay);
+                sleep(delay).await;
+
+                delay = (delay * 2).min(RECONNECT_MAX);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/*
+Asks the relay which of the station's tracks are announced right now and returns the newest
+as (loop_counter, song_index). The publisher only keeps the current song announced.
+ */
+pub async fn live_edge(session: &Session, station_index: u16) -> Option<(u32, usize)> {
+    let mut announced = session.announced(Filter::Prefix(format!("station{}-", station_index)));
+    let mut active = Vec::new();
+
+    let read = async {
+        while let Some(announce) = announced.next().await {
+            match announce {
+                Announced::Active(m) => active.push(m.to_capture()),
+                Announced::Ended(m) => active.retain(|path| path != m.capture()),
+                Announced::Live => break,
+            }
+        }
+    };
+
+    // older relays might never say they're caught up
+    let _ = timeout(Duration::from_secs(2), read).await;
+
+    active
+        .iter()
+        .filter_map(|capture| {
+            let (loop_counter, song_index) = capture.split_once('-')?;
+            Some((loop_counter.parse().ok()?, song_index.parse().ok()?))
+        })
+        .max()
+}
+
+/*
+Plays the station's tracks one after the other, starting at position.
+Never returns, so the caller finds out the session went away through Session::closed.
+ */
+pub async fn play_station(session: &Session, station_index: u16, position: &mut (u32, usize), tx: &AudioTx, opus_tx: &OpusTx, events: &StationEvents) -> Infallible {
+    loop {
+        let (loop_counter, song_index) = *position;
+        let track_name = format!("station{}-{}-{}", station_index, loop_counter, song_index);
+        println!("Subscribing to track: {}", track_name);
+
+        let track = Track::new(track_name.clone());
+        let reader = session.subscribe(track.clone());
+
+        // look up what's playing so browsers can show it
+        let title_reader = session.subscribe(Track::new(format!("title-{}", track_name)));
+        let title = read_title(title_reader).await;
+
+        match sub_play_opus(reader, tx.clone(), opus_tx.clone(), events.clone(), title).await {
+            Ok(_) => {
+                println!("Finished playing track: {}", track_name);
+            }
+            Err(e) => {
+                eprintln!("Error playing track: {:?}. Retrying in 5s...", e);
+                events.offline();
+                sleep(Duration::from_secs(5)).await;
+            }
+        }
+
+        println!("Waiting 2 seconds before trying next track...");
+        sleep(Duration::from_secs(2)).await;
+
+        *position = match song_index + 1 {
+            next if next >= PLAYLIST_LEN => (loop_counter + 1, 0),
+            next => (loop_counter, next),
+        };
+    }
+}
+
+// resolves on ctrl-c, or SIGTERM from docker/systemd
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let config = Config::parse();
@@ -210,13 +387,13 @@ async fn main() -> anyhow::Result<()> {
 
     let tls = config.tls.load()?;
     let quic = quic::Endpoint::new(quic::Config { bind: config.bind, tls })?;
-    let session = quic.client.connect(config.url).await?;
-    let mut session = moq_transfork::Session::connect(session).await?;
 
     let (tx, _) = tokio::sync::broadcast::channel::<Vec<u8>>(100);
+    let (opus_tx, _) = tokio::sync::broadcast::channel::<Bytes>(100);
 
     match config.role {
         Cmd::Publish => {
+            let mut session = connect(&quic.client, &config.url).await?;
             let mut playlist = vec![];
 
             if config.station_index == 1 {
@@ -250,9 +427,16 @@ async fn main() -> anyhow::Result<()> {
 
                     let track = Track::new(track_name.clone());
                     let (writer, reader) = track.produce();
+                    writer.set_ttl(Some(AUDIO_TTL));
 
                     session.publish(reader.clone()).context("failed to announce broadcast")?;
 
+                    // announce the song title on its own track, read by read_title when the song starts
+                    let title_track = Track::new(format!("title-{}", track_name));
+                    let (mut title_writer, title_reader) = title_track.produce();
+                    session.publish(title_reader).context("failed to announce title track")?;
+                    title_writer.create_group(0).write_frame(song.clone());
+
                     let song_path = format!("songs/{}.mp3", song);
 
                     File::open(&song_path).await
@@ -274,39 +458,54 @@ async fn main() -> anyhow::Result<()> {
             let port = 3030 + config.station_index - 1;
             println!("Starting WebSocket server on port {}", port);
 
-            tokio::spawn(run_ws_server(tx.clone(), station_id, port));
+            let events = StationEvents::new(station_id.clone());
+            let assets = Assets::new(config.assets_dir.clone());
 
-            let mut song_index = 0;
-            let mut loop_counter = 0;
-            let playlist_len = 5;
+            // package the station for HLS players alongside the live websocket
+            let hls = Hls::new(config.hls_segment_ms, config.hls_window);
+            tokio::spawn(hls.clone().run(opus_tx.clone()));
 
-            loop {
-                let track_name = format!("station{}-{}-{}", config.station_index, loop_counter, song_index);
-                println!("Subscribing to track: {}", track_name);
+            // flipped to true on SIGTERM/ctrl-c so the web server can drain
+            let (shutdown_tx, shutdown) = watch::channel(false);
+            let server = tokio::spawn(run_ws_server(tx.clone(), opus_tx.clone(), events.clone(), hls.clone(), assets, shutdown, station_id, port));
 
-                let track = Track::new(track_name.clone());
-                let reader = session.subscribe(track.clone());
+            let gateway = async {
+                let mut position = (0, 0);
 
-                match sub_play_opus(reader, tx.clone()).await {
-                    Ok(_) => {
-                        println!("Finished playing track: {}", track_name);
-                    }
-                    Err(e) => {
-                        eprintln!("Error playing track: {:?}. Retrying in 5s...", e);
-                        sleep(Duration::from_secs(5)).await;
+                loop {
+                    let session = reconnect(&quic.client, &config.url, &events).await;
+
+                    // pick up wherever the station is now, not where we were before the outage
+                    if let Some(live) = live_edge(&session, config.station_index).await {
+                        println!("Resuming at live track station{}-{}-{}", config.station_index, live.0, live.1);
+                        position = live;
                     }
-                }
 
-                println!("Waiting 2 seconds before trying next track...");
-                sleep(Duration::from_secs(2)).await;
+                    let err = tokio::select! {
+                        err = session.closed() => err,
+                        never = play_station(&session, config.station_index, &mut position, &tx, &opus_tx, &events) => match never {},
+                    };
+
+                    eprintln!("Lost connection to relay: {}", err);
+                    events.offline();
 
-                song_index += 1;
-                if song_index >= playlist_len {
-                    song_index = 0;
-                    loop_counter += 1;
+                    // whatever we play next doesn't follow on from the last segment
+                    hls.reconnected();
                 }
+            };
+
+            tokio::select! {
+                _ = gateway => {}
+                _ = shutdown_signal() => println!("Shutting down, draining listeners..."),
             }
 
+            events.offline();
+            let _ = shutdown_tx.send(true);
+
+            // SSE and HTTP audio streams never end on their own, so don't wait forever
+            if timeout(DRAIN_TIMEOUT, server).await.is_err() {
+                println!("Some listeners did not disconnect in time");
+            }
         }
     }
 
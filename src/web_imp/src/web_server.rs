@@ -7,15 +7,24 @@ use axum::{
 };
 
 use std::{net::SocketAddr, sync::Arc};
+use axum::http::{HeaderMap, Uri};
 use axum::response::Html;
-use tokio::sync::{broadcast, Mutex};
+use axum::extract::ws::{close_code, CloseFrame};
+use tokio::sync::{broadcast, watch, Mutex};
 use futures::{SinkExt, StreamExt};
-use tokio::fs;
 use tokio::net::TcpListener;
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
+use tower_http::compression::CompressionLayer;
+
+use crate::assets::Assets;
+use crate::events::{sse_handler, StationEvents};
+use crate::hls::{hls_handler, Hls};
+use crate::icecast::{stream_handler, OpusTx};
 
 pub type AudioTx = broadcast::Sender<Vec<u8>>;
+// becomes true once the gateway is shutting down
+pub type Shutdown = watch::Receiver<bool>;
 static BUFFER_TARGET: u32 = 1000;
 
 // data for synchronization
@@ -30,25 +39,49 @@ struct StreamInfo {
 /*
 Starts websocket server for clients to connect to
  */
-pub async fn run_ws_server(tx: AudioTx, ws_pathname: String, port: u16) {
+pub async fn run_ws_server(tx: AudioTx, opus_tx: OpusTx, events: StationEvents, hls: Hls, assets: Assets, shutdown: Shutdown, ws_pathname: String, port: u16) {
     //will be used to keep track of clients connected to the station
     let clients = Arc::new(Mutex::new(HashMap::<String, u64>::new()));
     let clients_clone = clients.clone();
+    let events_clone = events.clone();
+    let events_stream = events.clone();
+    let station = ws_pathname.clone();
+    let shutdown_ws = shutdown.clone();
 
 
     // Only set up the route for the specified WebSocket path
     let app = Router::new()
-        .route("/", get(serve_index))
         .route(&format!("/ws/{ws_pathname}"), get(move |ws, ext| {
-            ws_handler(ws, ext, clients_clone.clone())
+            ws_handler(ws, ext, clients_clone.clone(), events_clone.clone(), shutdown_ws.clone())
+        }))
+        // now-playing and station events, separate from the audio websocket
+        .route(&format!("/events/{ws_pathname}"), get(move || sse_handler(events.clone())))
+        // plain HTTP Ogg/Opus stream for media players that can't do websockets
+        .route(&format!("/{ws_pathname}.ogg"), get(move |headers| {
+            stream_handler(headers, opus_tx.clone(), events_stream.clone(), station.clone())
         }))
+        // fMP4 HLS for CDNs and devices that only speak plain HTTP
+        .route(&format!("/hls/{ws_pathname}/:file"), get(move |file| hls_handler(file, hls.clone())))
         .route("/stats", get(move || check_client_count(clients.clone())))
-        .layer(Extension(Arc::new(tx)));
+        .layer(Extension(Arc::new(tx)))
+        // anything else is part of the web UI (index.html, logo, ...)
+        // compression only applies here so it stays out of the way of the audio websocket
+        .fallback_service(
+            get(move |uri: Uri, headers: HeaderMap| async move { assets.serve(uri.path(), &headers).await })
+                .layer(CompressionLayer::new()),
+        );
     println!("Starting WebSocket server on port {}, pathname {}", port, ws_pathname);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     let listener = TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // stop accepting new connections once shutdown starts, and wait for the open ones to finish
+    let mut shutdown = shutdown;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.wait_for(|done| *done).await;
+        })
+        .await
+        .unwrap();
 }
 
 /*
@@ -59,20 +92,24 @@ async fn check_client_count(clients: Arc<Mutex<HashMap<String, u64>>>) -> impl I
     Html(format!("<h1>Connected Clients: {}</h1>", clients.len()))
 }
 
-async fn ws_handler(ws: WebSocketUpgrade, Extension(tx): Extension<Arc<AudioTx>>, clients: Arc<Mutex<HashMap<String, u64>>>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, tx, clients))
+async fn ws_handler(ws: WebSocketUpgrade, Extension(tx): Extension<Arc<AudioTx>>, clients: Arc<Mutex<HashMap<String, u64>>>, events: StationEvents, shutdown: Shutdown) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_socket(socket, tx, clients, events, shutdown))
 }
 
 /*
 TO-DO: Add receiving on client add for the info this function is sending
  */
-async fn handle_socket(mut socket: WebSocket, tx: Arc<AudioTx>, clients: Arc<Mutex<HashMap<String, u64>>>) {
+async fn handle_socket(mut socket: WebSocket, tx: Arc<AudioTx>, clients: Arc<Mutex<HashMap<String, u64>>>, events: StationEvents, mut shutdown: Shutdown) {
     //use uuid to make unique ids for each client
     let client_id = uuid::Uuid::new_v4().to_string();
 
     // add client to map, alongside timestamp to compare against
-    let mut clients_map = clients.lock().await;
-    clients_map.insert(client_id.clone(), chrono::Utc::now().timestamp_millis() as u64);
+    // (scoped so the lock isn't held for the rest of the connection)
+    {
+        let mut clients_map = clients.lock().await;
+        clients_map.insert(client_id.clone(), chrono::Utc::now().timestamp_millis() as u64);
+        events.listeners(clients_map.len());
+    }
     println!("Client connected: {}", client_id);
 
     // msg channel
@@ -114,21 +151,39 @@ async fn handle_socket(mut socket: WebSocket, tx: Arc<AudioTx>, clients: Arc<Mut
         //client disconnect
         let mut clients_map = clients_clone.lock().await;
         clients_map.remove(&client_id_clone);
+        events.listeners(clients_map.len());
         println!("Client disconnected: {}", client_id_clone);
     });
 
     // audio stream
     let mut sequence: u32 = 0; //initialize sequence number
+    let events_audio = events.clone();
     tokio::spawn(async move {
-        while let Ok(pcm) = rx.recv().await {
-            // send synchronization info every 24-60
-            // *********(CHANGE THIS NUMBER LATER ON idk what it should rlly be)***********
+        loop {
+            let pcm = tokio::select! {
+                pcm = rx.recv() => match pcm {
+                    Ok(pcm) => pcm,
+                    Err(_) => break,
+                },
+                // say goodbye properly so the browser knows to reconnect
+                _ = shutdown.changed() => {
+                    let close = CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "gateway shutting down".into(),
+                    };
+                    let _ = sender.send(Message::Close(Some(close))).await;
+                    break;
+                }
+            };
+
+            // send synchronization info every 60 audio messages, numbered the same as the audio this client received
             if sequence % 60 == 0 {
                 let sync_info = StreamInfo {
                     timestamp: chrono::Utc::now().timestamp_millis() as u64,
                     sequence,
                     buffer_target: BUFFER_TARGET,
-                    metadata: Some("SYNCING MSG BEEPBOOP".to_string()),
+                    // the song currently playing, if the publisher told us
+                    metadata: events_audio.title(),
                 };
 
                 if sender.send(Message::Text(serde_json::to_string(&sync_info).unwrap())).await.is_err() {
@@ -151,10 +206,3 @@ async fn handle_socket(mut socket: WebSocket, tx: Arc<AudioTx>, clients: Arc<Mut
     //     _ = audio_stream_handler => {},
     // }
 }
-
-async fn serve_index() -> impl IntoResponse {
-    match fs::read_to_string("index.html").await {
-        Ok(content) => Html(content),
-        Err(_) => Html("<h1>index.html not found</h1>".to_string()),
-    }
-}
\ No newline at end of file